@@ -5,25 +5,25 @@ use std::sync::Arc;
 
 use http::{
     body::HttpBody, method::HttpMethod, request::HttpRequest, response::HttpResponse,
-    router::HttpRouter, server::HttpServer,
+    router::HttpRouter, server::HttpServer, status::StatusCode,
 };
 use tokio::fs::{self, File};
 
 // define a test handler
 async fn test(_req: http::request::HttpRequest) -> HttpResponse {
     let test = fs::read("./examples/basic_server/www/html/test.html").await.unwrap();
-    HttpResponse::new(200, "OK").with_body(HttpBody::from(&test))
+    HttpResponse::new(StatusCode::OK).with_body(HttpBody::from(&test))
 }
 
 // large file handler
 async fn stream_large_file_handler(_req: HttpRequest) -> HttpResponse {
     match File::open("./examples/basic_server/www/test_file.bin").await {
-        Ok(file) => HttpResponse::new(200, "OK")
+        Ok(file) => HttpResponse::new(StatusCode::OK)
             .with_streaming_body(file, 8192)
             .insert_header("Transfer-Encoding", "chunked"),
         Err(e) => {
             eprintln!("{e}");
-            HttpResponse::new(404, "Not Found").with_body("File not found".into())
+            HttpResponse::new(StatusCode::NOT_FOUND).with_body("File not found".into())
         }
     }
 }
@@ -39,7 +39,7 @@ async fn main() {
                     let home = fs::read("./examples/basic_server/www/html/home.html")
                         .await
                         .unwrap();
-                    HttpResponse::new(200, "OK").with_body(HttpBody::from(&home))
+                    HttpResponse::new(StatusCode::OK).with_body(HttpBody::from(&home))
                 })
             }),
         )
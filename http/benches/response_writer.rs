@@ -0,0 +1,184 @@
+//! Compare the coalesced response writer with one write per line
+//!
+//! Run with `cargo bench -p http --bench response_writer`. Writes are counted on
+//! an in-memory writer, each one is a syscall on a raw `TcpStream`; throughput
+//! is measured over a loopback connection.
+
+use std::{
+    io::IoSlice,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use http::{method::HttpMethod, response::HttpResponse, status::StatusCode};
+use tokio::{
+    io::{self, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+const ROUNDS: usize = 20_000;
+
+type MakeResponse = fn() -> HttpResponse;
+
+/// Discard the data, count the writes
+#[derive(Default)]
+struct CountingWriter {
+    writes: usize,
+}
+
+impl AsyncWrite for CountingWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.writes += 1;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.writes += 1;
+        Poll::Ready(Ok(bufs.iter().map(|buf| buf.len()).sum()))
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn small_response() -> HttpResponse {
+    HttpResponse::text("Hello, World!")
+        .insert_header("Server", "http-rs")
+        .insert_header("Cache-Control", "no-cache")
+        .insert_header("Connection", "keep-alive")
+}
+
+fn chunked_response() -> HttpResponse {
+    HttpResponse::new(StatusCode::OK)
+        .with_streaming_body(&[b'x'; 4096][..], 1024)
+        .insert_header("Content-Type", "application/octet-stream")
+}
+
+/// The previous writer: one `format!` and `write_all` per line, three writes per chunk
+async fn send_per_line<W>(response: &mut HttpResponse, writer: &mut W) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let line = format!("HTTP/1.1 {} {}\r\n", response.status(), response.reason());
+    writer.write_all(line.as_bytes()).await?;
+
+    let chunked = response.headers().has_token("Transfer-Encoding", "chunked");
+    if !chunked && let Some(length) = response.body().content_length() {
+        let line = format!("Content-Length: {length}\r\n");
+        writer.write_all(line.as_bytes()).await?;
+    }
+    for (key, value) in response.headers().iter() {
+        let line = format!("{key}: {value}\r\n");
+        writer.write_all(line.as_bytes()).await?;
+    }
+    writer.write_all(b"\r\n").await?;
+
+    while let Some(chunk) = response.body_mut().read_next().await? {
+        match chunked {
+            true => {
+                let size = format!("{:X}\r\n", chunk.len());
+                writer.write_all(size.as_bytes()).await?;
+                writer.write_all(&chunk).await?;
+                writer.write_all(b"\r\n").await?;
+            }
+            false => writer.write_all(&chunk).await?,
+        }
+    }
+    if chunked {
+        writer.write_all(b"0\r\n").await?;
+        writer.write_all(b"\r\n").await?;
+    }
+    writer.flush().await
+}
+
+#[derive(Clone, Copy)]
+enum Writer {
+    PerLine,
+    Coalesced,
+}
+
+async fn send(
+    writer: Writer,
+    mut response: HttpResponse,
+    stream: &mut (impl AsyncWrite + Unpin),
+    buf: &mut Vec<u8>,
+) {
+    match writer {
+        Writer::PerLine => send_per_line(&mut response, stream).await.unwrap(),
+        Writer::Coalesced => response
+            .send_with_buffer(stream, HttpMethod::Get, buf)
+            .await
+            .unwrap(),
+    }
+}
+
+async fn writes_per_response(writer: Writer, make: MakeResponse) -> usize {
+    let mut counter = CountingWriter::default();
+    send(writer, make(), &mut counter, &mut Vec::new()).await;
+    counter.writes
+}
+
+/// Send `ROUNDS` responses over loopback, return the elapsed time
+async fn loopback(writer: Writer, make: MakeResponse) -> Duration {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let drain = tokio::spawn(async move {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let mut sink = vec![0u8; 64 * 1024];
+        while stream.read(&mut sink).await.unwrap() > 0 {}
+    });
+
+    let (mut stream, _) = listener.accept().await.unwrap();
+    stream.set_nodelay(true).unwrap();
+    let mut buf = Vec::new();
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        send(writer, make(), &mut stream, &mut buf).await;
+    }
+    let elapsed = start.elapsed();
+
+    drop(stream);
+    drain.await.unwrap();
+    elapsed
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let cases: [(&str, MakeResponse); 2] = [
+        ("small text", small_response),
+        ("chunked 4 KiB", chunked_response),
+    ];
+
+    for (name, make) in cases {
+        println!("{name}:");
+        for (label, writer) in [
+            ("per line", Writer::PerLine),
+            ("coalesced", Writer::Coalesced),
+        ] {
+            let writes = writes_per_response(writer, make).await;
+            let elapsed = loopback(writer, make).await;
+            let rate = ROUNDS as f64 / elapsed.as_secs_f64();
+            println!("  {label:>9}: {writes:>2} writes/response, {rate:>9.0} responses/s");
+        }
+    }
+}
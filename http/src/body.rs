@@ -1,17 +1,64 @@
-use std::pin::Pin;
+use std::{
+    fmt,
+    future::Future,
+    mem,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, ready},
+};
 
-use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::{
+    io::{self, AsyncRead, ReadBuf},
+    sync::{Mutex, mpsc, oneshot},
+};
 
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
+
+use crate::{
+    bytes::Bytes,
+    connect::{ConnectionReader, Generation, request_over},
+    headers::ContentType,
+    request::HttpRequest,
+    response::HttpResponse,
+    status::StatusCode,
+    stream::{Stream, StreamExt},
+};
+
+/// Default size limit when collecting a whole body
+pub const DEFAULT_BODY_LIMIT: usize = 2 * 1024 * 1024;
+
+/// Default number of chunks buffered by `HttpBody::channel`
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 16;
+
+/// A boxed stream of body chunks
+pub type BodyStream = Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send + Sync + 'static>>;
+
+/// Request or response body
+///
+/// Chunks are read with `read_next`, or through the `Stream` and `AsyncRead`
+/// implementations, so a body can be piped with `tokio::io::copy`.
 #[derive(Default)]
 pub enum HttpBody {
     /// complete data in memory
-    InMemory { data: Vec<u8> },
+    InMemory { data: Bytes },
     /// streaming data
     Streaming {
         reader: Pin<Box<dyn AsyncRead + Send + Sync + 'static>>,
+        /// Data to yield before reading, also the buffer for the next chunk
         read_buf: Vec<u8>,
         buffer_size: usize,
+        /// Total length, if known in advance
+        length: Option<usize>,
+    },
+    /// chunks from a stream, such as another body
+    Stream {
+        stream: BodyStream,
+        /// Rest of a chunk partly consumed by `AsyncRead`
+        remaining: Bytes,
     },
+    /// request body read from the connection
+    Incoming(IncomingBody),
     /// empty body
     #[default]
     Empty,
@@ -22,71 +69,581 @@ impl HttpBody {
         HttpBody::Empty
     }
 
-    pub fn from_data(data: Vec<u8>) -> Self {
+    pub fn from_data(data: impl Into<Bytes>) -> Self {
+        let data = data.into();
         match data.is_empty() {
             true => HttpBody::Empty,
             false => HttpBody::InMemory { data },
         }
     }
 
-    /// Create a new streaming body
+    /// Create a new streaming body of unknown length
     pub fn from_reader<R>(reader: R, buffer_size: usize) -> Self
     where
         R: AsyncRead + Send + Sync + 'static,
     {
         HttpBody::Streaming {
             reader: Box::pin(reader),
-            read_buf: Vec::with_capacity(buffer_size),
+            read_buf: Vec::new(),
+            buffer_size,
+            length: None,
+        }
+    }
+
+    /// Create a new streaming body which yields exactly `length` bytes
+    pub fn from_sized_reader<R>(reader: R, length: usize, buffer_size: usize) -> Self
+    where
+        R: AsyncRead + Send + Sync + 'static,
+    {
+        HttpBody::Streaming {
+            reader: Box::pin(reader),
+            read_buf: Vec::new(),
             buffer_size,
+            length: Some(length),
         }
     }
 
+    /// Create a body of unknown length from a stream of chunks
+    pub fn from_stream<S>(stream: S) -> Self
+    where
+        S: Stream<Item = io::Result<Bytes>> + Send + Sync + 'static,
+    {
+        HttpBody::Stream {
+            stream: Box::pin(stream),
+            remaining: Bytes::new(),
+        }
+    }
+
+    /// Create a body of unknown length fed through the returned sender
+    ///
+    /// Up to `DEFAULT_CHANNEL_CAPACITY` chunks are buffered, after that `send`
+    /// waits until the body is read.
+    pub fn channel() -> (BodySender, Self) {
+        HttpBody::channel_with_capacity(DEFAULT_CHANNEL_CAPACITY)
+    }
+
+    /// Same as `channel`, with room for `capacity` chunks
+    pub fn channel_with_capacity(capacity: usize) -> (BodySender, Self) {
+        let (sender, receiver) = mpsc::channel(capacity);
+        let (abort_sender, abort) = oneshot::channel();
+
+        let body = ChannelBody {
+            receiver,
+            abort: Some(abort),
+            flushed: None,
+        };
+        let sender = BodySender {
+            sender,
+            abort: abort_sender,
+        };
+
+        (sender, HttpBody::from_stream(body))
+    }
+
     /// Read the next chunk of data from the body
-    pub async fn read_next(&mut self) -> tokio::io::Result<Option<Vec<u8>>> {
+    pub async fn read_next(&mut self) -> io::Result<Option<Bytes>> {
+        self.next().await.transpose()
+    }
+
+    pub fn is_streaming(&self) -> bool {
+        matches!(
+            self,
+            HttpBody::Streaming { .. } | HttpBody::Stream { .. } | HttpBody::Incoming(_)
+        )
+    }
+
+    pub fn content_length(&self) -> Option<usize> {
         match self {
-            HttpBody::InMemory { data } => {
-                if data.is_empty() {
-                    Ok(None)
-                } else {
-                    let res = Ok(Some(data.clone()));
-                    data.clear();
-                    res
+            HttpBody::InMemory { data } => Some(data.len()),
+            HttpBody::Streaming { length, .. } => *length,
+            HttpBody::Stream { .. } => None,
+            HttpBody::Incoming(incoming) => incoming.content_length,
+            HttpBody::Empty => None,
+        }
+    }
+
+    /// Read the whole body, fail if it is larger than `limit`
+    ///
+    /// A body with a single chunk is returned without copying.
+    pub async fn collect(&mut self, limit: usize) -> Result<Bytes, BodyError> {
+        if let Some(length) = self.content_length()
+            && length > limit
+        {
+            return Err(BodyError::LimitExceeded(limit));
+        }
+
+        let mut first = Bytes::new();
+        let mut data = Vec::new();
+        let mut size = 0;
+        while let Some(chunk) = self.read_next().await? {
+            size += chunk.len();
+            if size > limit {
+                return Err(BodyError::LimitExceeded(limit));
+            }
+            match (first.is_empty(), data.is_empty()) {
+                (true, true) => first = chunk,
+                (false, true) => {
+                    data.extend_from_slice(&first);
+                    data.extend_from_slice(&chunk);
                 }
+                _ => data.extend_from_slice(&chunk),
+            }
+        }
+
+        Ok(match data.is_empty() {
+            true => first,
+            false => Bytes::from(data),
+        })
+    }
+
+    /// Read the whole body as text in `charset`, UTF-8 if `None`
+    ///
+    /// Supported charsets are UTF-8, US-ASCII and ISO-8859-1.
+    pub async fn text(&mut self, charset: Option<&str>, limit: usize) -> Result<String, BodyError> {
+        let data = self.collect(limit).await?;
+        decode_text(data, charset)
+    }
+
+    /// Read the whole body as JSON and deserialize it into `T`
+    #[cfg(feature = "serde")]
+    pub async fn json<T: DeserializeOwned>(&mut self, limit: usize) -> Result<T, BodyError> {
+        let data = self.collect(limit).await?;
+        serde_json::from_slice(&data).map_err(|e| BodyError::Invalid(e.to_string()))
+    }
+
+    /// Put back the unconsumed rest of a chunk, it is yielded next
+    fn unread(&mut self, rest: Bytes) {
+        match self {
+            HttpBody::InMemory { data } => *data = rest,
+            HttpBody::Streaming { read_buf, .. } => *read_buf = rest.into_vec(),
+            HttpBody::Stream { remaining, .. } => *remaining = rest,
+            HttpBody::Incoming(incoming) => incoming.remaining = rest,
+            HttpBody::Empty => *self = HttpBody::InMemory { data: rest },
+        }
+    }
+}
+
+/// Writing half of `HttpBody::channel`
+///
+/// Dropping the sender ends the body, `abort` fails it instead.
+#[derive(Debug)]
+pub struct BodySender {
+    sender: mpsc::Sender<ChannelMessage>,
+    abort: oneshot::Sender<()>,
+}
+
+#[derive(Debug)]
+enum ChannelMessage {
+    Data(Bytes),
+    /// Acknowledged once the writer has flushed everything before it
+    Flush(oneshot::Sender<()>),
+}
+
+impl BodySender {
+    /// Send a chunk, wait if the buffer is full
+    ///
+    /// Fails with `BrokenPipe` once the body is dropped, such as when the
+    /// client disconnected.
+    pub async fn send(&self, data: impl Into<Bytes>) -> io::Result<()> {
+        let data = data.into();
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        self.sender
+            .send(ChannelMessage::Data(data))
+            .await
+            .map_err(|_| body_dropped())
+    }
+
+    /// Wait until everything sent so far is written and flushed to the client
+    pub async fn flush(&self) -> io::Result<()> {
+        let (ack, flushed) = oneshot::channel();
+        self.sender
+            .send(ChannelMessage::Flush(ack))
+            .await
+            .map_err(|_| body_dropped())?;
+
+        flushed.await.map_err(|_| body_dropped())
+    }
+
+    /// Fail the body, the response is cut off and the connection closed
+    pub fn abort(self) {
+        let _ = self.abort.send(());
+    }
+
+    /// Whether the body was dropped, sending would fail
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+}
+
+fn body_dropped() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "the body was dropped")
+}
+
+/// Reading half of `HttpBody::channel`
+///
+/// A flush is yielded as an empty chunk, which the response writer takes as a
+/// request to flush.
+struct ChannelBody {
+    receiver: mpsc::Receiver<ChannelMessage>,
+    abort: Option<oneshot::Receiver<()>>,
+    /// Flush to acknowledge when the writer asks for the next chunk
+    flushed: Option<oneshot::Sender<()>>,
+}
+
+impl Stream for ChannelBody {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(ack) = self.flushed.take() {
+            let _ = ack.send(());
+        }
+
+        if let Some(abort) = self.abort.as_mut()
+            && let Poll::Ready(result) = Pin::new(abort).poll(cx)
+        {
+            self.abort = None;
+            // an error means the sender was dropped without aborting
+            if result.is_ok() {
+                self.receiver.close();
+                return Poll::Ready(Some(Err(io::Error::new(
+                    io::ErrorKind::ConnectionAborted,
+                    "the body was aborted",
+                ))));
+            }
+        }
+
+        match ready!(self.receiver.poll_recv(cx)) {
+            Some(ChannelMessage::Data(data)) => Poll::Ready(Some(Ok(data))),
+            Some(ChannelMessage::Flush(ack)) => {
+                self.flushed = Some(ack);
+                Poll::Ready(Some(Ok(Bytes::new())))
             }
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+/// Error when collecting a whole body
+#[derive(Debug)]
+pub enum BodyError {
+    /// The body is larger than the limit
+    LimitExceeded(usize),
+    /// The content type or charset is not supported
+    UnsupportedMediaType(Option<String>),
+    /// The body can't be decoded or deserialized
+    Invalid(String),
+    /// IO error while reading the body
+    IOError(io::Error),
+}
+
+impl BodyError {
+    /// Status of the response to send for this error
+    pub fn status(&self) -> StatusCode {
+        match self {
+            BodyError::LimitExceeded(_) => StatusCode::CONTENT_TOO_LARGE,
+            BodyError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            BodyError::IOError(e) if e.kind() == io::ErrorKind::TimedOut => {
+                StatusCode::REQUEST_TIMEOUT
+            }
+            BodyError::Invalid(_) | BodyError::IOError(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+/// A message which can be sent to the client
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BodyError::LimitExceeded(limit) => write!(f, "body is larger than {limit} bytes"),
+            BodyError::UnsupportedMediaType(Some(content_type)) => {
+                write!(f, "unsupported content type: {content_type}")
+            }
+            BodyError::UnsupportedMediaType(None) => f.write_str("missing content type"),
+            BodyError::Invalid(message) => f.write_str(message),
+            BodyError::IOError(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl From<io::Error> for BodyError {
+    fn from(value: io::Error) -> Self {
+        BodyError::IOError(value)
+    }
+}
+
+impl From<BodyError> for HttpResponse {
+    fn from(value: BodyError) -> Self {
+        let mut response = HttpResponse::text(value.to_string());
+        response.set_status(value.status());
+        response
+    }
+}
+
+fn decode_text(data: Bytes, charset: Option<&str>) -> Result<String, BodyError> {
+    let charset = charset.map(|charset| charset.to_ascii_lowercase());
+    match charset.as_deref() {
+        None | Some("utf-8" | "utf8") => String::from_utf8(data.into_vec())
+            .map_err(|_| BodyError::Invalid("body is not valid UTF-8".to_string())),
+        Some("us-ascii" | "ascii") => match data.is_ascii() {
+            true => Ok(data.iter().map(|&b| b as char).collect()),
+            false => Err(BodyError::Invalid("body is not valid ASCII".to_string())),
+        },
+        // every byte is the code point of the same value
+        Some("iso-8859-1" | "latin1" | "l1") => Ok(data.iter().map(|&b| b as char).collect()),
+        Some(other) => Err(BodyError::UnsupportedMediaType(Some(format!(
+            "charset={other}"
+        )))),
+    }
+}
+
+impl HttpRequest {
+    /// Read the whole body of at most `limit` bytes
+    pub async fn bytes(&mut self, limit: usize) -> Result<Bytes, BodyError> {
+        match self.body.as_mut() {
+            Some(body) => body.collect(limit).await,
+            None => Ok(Bytes::new()),
+        }
+    }
+
+    /// Read the whole body as text, decoded with the `Content-Type` charset
+    pub async fn text(&mut self, limit: usize) -> Result<String, BodyError> {
+        let content_type = self.content_type()?;
+        let charset = content_type.as_ref().and_then(|ct| ct.charset());
+        let data = self.bytes(limit).await?;
+        decode_text(data, charset)
+    }
+
+    /// Read a JSON body and deserialize it into `T`
+    ///
+    /// The content type must be `application/json` or a `+json` type.
+    #[cfg(feature = "serde")]
+    pub async fn json<T: DeserializeOwned>(&mut self, limit: usize) -> Result<T, BodyError> {
+        let is_json = self.content_type()?.is_some_and(|ct| {
+            let media_type = ct.media_type();
+            let charset = ct.charset().unwrap_or("utf-8");
+            (media_type == "application/json" || media_type.ends_with("+json"))
+                && charset.eq_ignore_ascii_case("utf-8")
+        });
+        if !is_json {
+            return Err(BodyError::UnsupportedMediaType(
+                self.headers.get("Content-Type").cloned(),
+            ));
+        }
+
+        let data = self.bytes(limit).await?;
+        serde_json::from_slice(&data).map_err(|e| BodyError::Invalid(e.to_string()))
+    }
+
+    fn content_type(&self) -> Result<Option<ContentType>, BodyError> {
+        self.headers
+            .try_typed::<ContentType>()
+            .map_err(|_| BodyError::UnsupportedMediaType(self.headers.get("Content-Type").cloned()))
+    }
+}
+
+/// JSON body deserialized into `T`
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Json<T>(pub T);
+
+#[cfg(feature = "serde")]
+impl<T: DeserializeOwned> Json<T> {
+    /// Read a JSON body of at most `limit` bytes
+    pub async fn from_request(req: &mut HttpRequest, limit: usize) -> Result<Self, BodyError> {
+        req.json(limit).await.map(Json)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl Stream for HttpBody {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.get_mut() {
+            HttpBody::InMemory { data } => match data.is_empty() {
+                true => Poll::Ready(None),
+                false => Poll::Ready(Some(Ok(mem::take(data)))),
+            },
             HttpBody::Streaming {
                 reader,
                 read_buf,
                 buffer_size,
+                ..
             } => {
-                read_buf.clear();
+                if !read_buf.is_empty() {
+                    return Poll::Ready(Some(Ok(Bytes::from(mem::take(read_buf)))));
+                }
+
+                // read into the buffer, which then becomes the chunk without a copy
                 read_buf.resize(*buffer_size, 0);
-                match AsyncReadExt::read(reader, read_buf).await {
+                let mut buf = ReadBuf::new(read_buf);
+                let result = reader.as_mut().poll_read(cx, &mut buf);
+                let filled = buf.filled().len();
+
+                match result {
+                    Poll::Ready(Ok(())) if filled > 0 => {
+                        read_buf.truncate(filled);
+                        Poll::Ready(Some(Ok(Bytes::from(mem::take(read_buf)))))
+                    }
                     // end of stream
-                    Ok(0) => Ok(None),
-                    Ok(n) => {
-                        read_buf.truncate(n);
-                        Ok(Some(read_buf.clone()))
+                    Poll::Ready(Ok(())) => {
+                        read_buf.clear();
+                        Poll::Ready(None)
+                    }
+                    Poll::Ready(Err(e)) => {
+                        read_buf.clear();
+                        Poll::Ready(Some(Err(e)))
+                    }
+                    Poll::Pending => {
+                        read_buf.clear();
+                        Poll::Pending
                     }
-                    Err(e) => Err(e),
                 }
             }
-            HttpBody::Empty => Ok(None),
+            HttpBody::Stream { stream, remaining } => match remaining.is_empty() {
+                true => stream.as_mut().poll_next(cx),
+                false => Poll::Ready(Some(Ok(mem::take(remaining)))),
+            },
+            HttpBody::Incoming(incoming) => incoming.poll_next(cx),
+            HttpBody::Empty => Poll::Ready(None),
         }
     }
 
-    pub fn is_streaming(&self) -> bool {
-        matches!(self, HttpBody::Streaming { .. })
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            HttpBody::InMemory { .. } => (1, Some(1)),
+            HttpBody::Empty => (0, Some(0)),
+            _ => (0, None),
+        }
     }
+}
 
-    pub fn content_length(&self) -> Option<usize> {
-        match self {
-            HttpBody::InMemory { data } => Some(data.len()),
-            HttpBody::Streaming { .. } => None,
-            HttpBody::Empty => None,
+impl AsyncRead for HttpBody {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        // read straight into the caller's buffer when nothing is pending
+        if let HttpBody::Streaming {
+            reader, read_buf, ..
+        } = this
+            && read_buf.is_empty()
+        {
+            return reader.as_mut().poll_read(cx, buf);
+        }
+
+        loop {
+            let Some(mut chunk) = ready!(Pin::new(&mut *this).poll_next(cx)).transpose()? else {
+                return Poll::Ready(Ok(()));
+            };
+            if chunk.is_empty() {
+                continue;
+            }
+
+            let n = chunk.len().min(buf.remaining());
+            buf.put_slice(&chunk.split_to(n));
+            if !chunk.is_empty() {
+                this.unread(chunk);
+            }
+
+            return Poll::Ready(Ok(()));
         }
     }
 }
 
+type ReadFuture = Pin<Box<dyn Future<Output = io::Result<Option<Vec<u8>>>> + Send + Sync>>;
+
+/// Request body which is read from the connection on demand
+///
+/// If the client sent `Expect: 100-continue`, the interim response is
+/// requested from the connection on the first read.
+pub struct IncomingBody {
+    /// Read side of the connection
+    reader: Arc<Mutex<ConnectionReader>>,
+    /// The request this is the body of
+    generation: Generation,
+    /// Ask the connection to send `100 Continue`
+    expect_continue: Option<oneshot::Sender<()>>,
+    /// Value of the `Content-Length` header, if any
+    content_length: Option<usize>,
+    /// Read of the next chunk in progress
+    pending: Option<ReadFuture>,
+    /// Bytes read so far
+    received: usize,
+    /// The end or an error was read, the connection isn't asked again
+    ended: bool,
+    /// Rest of a chunk partly consumed by `AsyncRead`
+    remaining: Bytes,
+}
+
+impl IncomingBody {
+    pub(crate) fn new(
+        reader: Arc<Mutex<ConnectionReader>>,
+        generation: Generation,
+        expect_continue: Option<oneshot::Sender<()>>,
+        content_length: Option<usize>,
+    ) -> Self {
+        IncomingBody {
+            reader,
+            generation,
+            expect_continue,
+            content_length,
+            pending: None,
+            received: 0,
+            ended: false,
+            remaining: Bytes::new(),
+        }
+    }
+
+    fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<io::Result<Bytes>>> {
+        if !self.remaining.is_empty() {
+            return Poll::Ready(Some(Ok(mem::take(&mut self.remaining))));
+        }
+
+        // the reader may be watching for the client to close by now
+        if self.ended || self.content_length == Some(self.received) {
+            return Poll::Ready(None);
+        }
+
+        if let Some(tx) = self.expect_continue.take() {
+            // the connection may have stopped waiting, the client will send anyway
+            let _ = tx.send(());
+        }
+
+        let future = self.pending.get_or_insert_with(|| {
+            let reader = self.reader.clone();
+            let generation = self.generation.clone();
+            Box::pin(async move {
+                // the reader may be busy with the next request for long
+                if !generation.is_current() {
+                    return Err(request_over());
+                }
+                reader.lock().await.read_body_of(&generation).await
+            })
+        });
+        let result = ready!(future.as_mut().poll(cx));
+        self.pending = None;
+        match &result {
+            Ok(Some(chunk)) => self.received += chunk.len(),
+            Ok(None) | Err(_) => self.ended = true,
+        }
+
+        Poll::Ready(result.map(|chunk| chunk.map(Bytes::from)).transpose())
+    }
+}
+
 impl std::fmt::Debug for HttpBody {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -94,13 +651,25 @@ impl std::fmt::Debug for HttpBody {
             HttpBody::Streaming {
                 read_buf,
                 buffer_size,
+                length,
                 ..
             } => f
                 .debug_struct("Streaming")
                 .field("read_buf", read_buf)
                 .field("buffer_size", buffer_size)
+                .field("length", length)
                 .field("reader", &"<dyn AsyncRead>")
                 .finish(),
+            HttpBody::Stream { remaining, .. } => f
+                .debug_struct("Stream")
+                .field("remaining", remaining)
+                .field("stream", &"<dyn Stream>")
+                .finish(),
+            HttpBody::Incoming(incoming) => f
+                .debug_struct("Incoming")
+                .field("content_length", &incoming.content_length)
+                .field("expect_continue", &incoming.expect_continue.is_some())
+                .finish(),
             HttpBody::Empty => write!(f, "Empty"),
         }
     }
@@ -109,25 +678,37 @@ impl std::fmt::Debug for HttpBody {
 impl From<&str> for HttpBody {
     fn from(value: &str) -> Self {
         HttpBody::InMemory {
-            data: value.as_bytes().to_vec(),
+            data: Bytes::copy_from_slice(value.as_bytes()),
         }
     }
 }
 
+impl From<String> for HttpBody {
+    fn from(value: String) -> Self {
+        HttpBody::InMemory { data: value.into() }
+    }
+}
+
 impl From<Vec<u8>> for HttpBody {
     fn from(value: Vec<u8>) -> Self {
-        HttpBody::InMemory { data: value }
+        HttpBody::InMemory { data: value.into() }
     }
 }
 
 impl From<&Vec<u8>> for HttpBody {
     fn from(value: &Vec<u8>) -> Self {
         HttpBody::InMemory {
-            data: value.clone(),
+            data: Bytes::copy_from_slice(value),
         }
     }
 }
 
+impl From<Bytes> for HttpBody {
+    fn from(value: Bytes) -> Self {
+        HttpBody::InMemory { data: value }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -187,4 +768,189 @@ mod tests {
             String::from_utf8_lossy(&data)
         );
     }
+
+    #[test]
+    async fn test_in_memory_chunk_is_shared() {
+        let data = Bytes::from(b"shared".to_vec());
+        let mut body = HttpBody::from(data.clone());
+
+        let chunk = body.read_next().await.unwrap().unwrap();
+        assert_eq!(chunk.as_ptr(), data.as_ptr());
+    }
+
+    #[test]
+    async fn test_async_read() {
+        use tokio::io::AsyncReadExt;
+
+        // smaller reads than the chunks, the rest is kept for the next read
+        let stream = crate::stream::iter([
+            Ok(Bytes::from("hello ")),
+            Ok(Bytes::new()),
+            Ok(Bytes::from("world")),
+        ]);
+        let mut body = HttpBody::from_stream(stream);
+        let mut first = [0u8; 4];
+        body.read_exact(&mut first).await.unwrap();
+        assert_eq!(&first, b"hell");
+
+        let mut rest = String::new();
+        body.read_to_string(&mut rest).await.unwrap();
+        assert_eq!(rest, "o world");
+
+        // pipe a body into any writer
+        let mut body = HttpBody::from_reader(&b"copied through"[..], 4);
+        let mut output = Vec::new();
+        tokio::io::copy(&mut body, &mut output).await.unwrap();
+        assert_eq!(output, b"copied through");
+    }
+
+    #[test]
+    async fn test_body_as_stream() {
+        // a body can be the source of another body
+        let source = HttpBody::from_reader(&b"abcdef"[..], 4);
+        let mut body = HttpBody::from_stream(source);
+        assert!(body.is_streaming());
+
+        let mut chunks = Vec::new();
+        while let Some(chunk) = body.next().await {
+            chunks.push(chunk.unwrap());
+        }
+        assert_eq!(chunks, [Bytes::from("abcd"), Bytes::from("ef")]);
+    }
+
+    #[test]
+    async fn test_collect() {
+        // a single chunk is not copied
+        let data = Bytes::from(b"single".to_vec());
+        let mut body = HttpBody::from(data.clone());
+        let collected = body.collect(DEFAULT_BODY_LIMIT).await.unwrap();
+        assert_eq!(collected.as_ptr(), data.as_ptr());
+
+        let mut body = HttpBody::from_reader(&b"several small chunks"[..], 4);
+        assert_eq!(body.collect(64).await.unwrap(), "several small chunks");
+        assert_eq!(HttpBody::new().collect(0).await.unwrap(), "");
+
+        // the known length is checked before reading
+        let mut body = HttpBody::from("too long");
+        assert!(matches!(
+            body.collect(4).await,
+            Err(BodyError::LimitExceeded(4))
+        ));
+        assert_eq!(body.content_length(), Some(8));
+
+        // an unknown length is checked while reading
+        let mut body = HttpBody::from_reader(&b"too long"[..], 3);
+        let err = body.collect(4).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::CONTENT_TOO_LARGE);
+    }
+
+    #[test]
+    async fn test_text_charset() {
+        let mut body = HttpBody::from("caf\u{e9}");
+        assert_eq!(body.text(None, 64).await.unwrap(), "caf\u{e9}");
+
+        let mut body = HttpBody::from(b"caf\xe9".to_vec());
+        assert_eq!(
+            body.text(Some("ISO-8859-1"), 64).await.unwrap(),
+            "caf\u{e9}"
+        );
+
+        let mut body = HttpBody::from(b"caf\xe9".to_vec());
+        let err = body.text(None, 64).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+
+        let mut body = HttpBody::from("text");
+        let err = body.text(Some("utf-16"), 64).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[test]
+    async fn test_request_text() {
+        let mut req = HttpRequest::from(
+            "POST /text HTTP/1.1\r\n\
+             Content-Type: text/plain; charset=latin1\r\n\
+             \r\n\
+             na\u{ef}ve",
+        );
+        // the UTF-8 `ï` read as two latin1 characters
+        assert_eq!(req.text(64).await.unwrap(), "na\u{c3}\u{af}ve");
+
+        let mut req = HttpRequest::from("GET / HTTP/1.1\r\n\r\n");
+        assert_eq!(req.text(64).await.unwrap(), "");
+    }
+
+    #[test]
+    async fn test_error_response() {
+        let mut response = HttpResponse::from(BodyError::LimitExceeded(16));
+        assert_eq!(response.status(), StatusCode::CONTENT_TOO_LARGE);
+
+        // read a whole response body
+        let body = response.body_mut().text(None, 64).await.unwrap();
+        assert_eq!(body, "body is larger than 16 bytes");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    async fn test_json() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct User {
+            name: String,
+        }
+
+        let mut req = HttpRequest::from(
+            "POST /users HTTP/1.1\r\n\
+             Content-Type: application/json\r\n\
+             \r\n\
+             {\"name\":\"Ferris\"}",
+        );
+        let Json(user) = Json::<User>::from_request(&mut req, 64).await.unwrap();
+        assert_eq!(user.name, "Ferris");
+
+        // `+json` types are accepted, invalid data is a bad request
+        let mut req = HttpRequest::from(
+            "POST /users HTTP/1.1\r\n\
+             Content-Type: application/merge-patch+json\r\n\
+             \r\n\
+             {\"name\":1}",
+        );
+        let err = req.json::<User>(64).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+
+        for content_type in ["text/plain", "application/json; charset=latin1"] {
+            let mut req = HttpRequest::from(format!(
+                "POST /users HTTP/1.1\r\nContent-Type: {content_type}\r\n\r\n{{}}"
+            ));
+            let err = req.json::<User>(64).await.unwrap_err();
+            assert_eq!(err.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        }
+
+        let mut body = HttpBody::from("[1,2,3]");
+        assert_eq!(body.json::<Vec<u32>>(64).await.unwrap(), [1, 2, 3]);
+    }
+
+    #[test]
+    async fn test_channel_backpressure() {
+        use std::time::Duration;
+
+        let (sender, mut body) = HttpBody::channel_with_capacity(1);
+        assert!(body.is_streaming());
+        assert_eq!(body.content_length(), None);
+
+        // the second chunk waits for room in the buffer
+        sender.send("first").await.unwrap();
+        let blocked = tokio::time::timeout(Duration::from_millis(10), sender.send("second"));
+        assert!(blocked.await.is_err());
+
+        assert_eq!(body.read_next().await.unwrap().unwrap(), "first");
+        sender.send("second").await.unwrap();
+        drop(sender);
+        assert_eq!(body.collect(64).await.unwrap(), "second");
+
+        // the client went away
+        let (sender, body) = HttpBody::channel();
+        drop(body);
+        assert!(sender.is_closed());
+        let err = sender.send("lost").await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
 }
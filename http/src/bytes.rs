@@ -0,0 +1,289 @@
+use std::{
+    borrow::Borrow,
+    fmt,
+    hash::{Hash, Hasher},
+    ops::{Bound, Deref, RangeBounds},
+    sync::Arc,
+};
+
+/// Cheaply cloneable, immutable bytes
+///
+/// Clones and slices share the same allocation, so a chunk can flow from the
+/// connection through handlers and back out without being copied.
+#[derive(Clone)]
+pub struct Bytes {
+    data: Shared,
+    start: usize,
+    end: usize,
+}
+
+#[derive(Clone)]
+enum Shared {
+    Static(&'static [u8]),
+    Vec(Arc<Vec<u8>>),
+}
+
+impl Bytes {
+    pub const fn new() -> Self {
+        Bytes::from_static(&[])
+    }
+
+    pub const fn from_static(data: &'static [u8]) -> Self {
+        Bytes {
+            data: Shared::Static(data),
+            start: 0,
+            end: data.len(),
+        }
+    }
+
+    pub fn copy_from_slice(data: &[u8]) -> Self {
+        Bytes::from(data.to_vec())
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// A sub-slice sharing the same allocation
+    ///
+    /// Panics if the range is out of bounds, like slice indexing.
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> Self {
+        let start = match range.start_bound() {
+            Bound::Included(&n) => n,
+            Bound::Excluded(&n) => n + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&n) => n + 1,
+            Bound::Excluded(&n) => n,
+            Bound::Unbounded => self.len(),
+        };
+        assert!(
+            start <= end && end <= self.len(),
+            "range {start}..{end} out of bounds for {} bytes",
+            self.len()
+        );
+
+        Bytes {
+            data: self.data.clone(),
+            start: self.start + start,
+            end: self.start + end,
+        }
+    }
+
+    /// Split off and return the first `at` bytes, `self` keeps the rest
+    pub fn split_to(&mut self, at: usize) -> Self {
+        let head = self.slice(..at);
+        self.start += at;
+        head
+    }
+
+    /// Split off and return the bytes from `at`, `self` keeps the first `at`
+    pub fn split_off(&mut self, at: usize) -> Self {
+        let tail = self.slice(at..);
+        self.end = self.start + at;
+        tail
+    }
+
+    /// Drop the first `n` bytes
+    pub fn advance(&mut self, n: usize) {
+        self.split_to(n);
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.end = self.start + len.min(self.len());
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Convert into a `Vec`, without copying if this is the only owner of the whole allocation
+    pub fn into_vec(self) -> Vec<u8> {
+        match self.data {
+            Shared::Vec(data) if self.start == 0 && self.end == data.len() => {
+                Arc::try_unwrap(data).unwrap_or_else(|data| data.to_vec())
+            }
+            _ => self.to_vec(),
+        }
+    }
+}
+
+impl Default for Bytes {
+    fn default() -> Self {
+        Bytes::new()
+    }
+}
+
+impl Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        let data: &[u8] = match &self.data {
+            Shared::Static(data) => data,
+            Shared::Vec(data) => data,
+        };
+        &data[self.start..self.end]
+    }
+}
+
+impl AsRef<[u8]> for Bytes {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl Borrow<[u8]> for Bytes {
+    fn borrow(&self) -> &[u8] {
+        self
+    }
+}
+
+impl From<Vec<u8>> for Bytes {
+    fn from(value: Vec<u8>) -> Self {
+        let end = value.len();
+        Bytes {
+            data: Shared::Vec(Arc::new(value)),
+            start: 0,
+            end,
+        }
+    }
+}
+
+impl From<String> for Bytes {
+    fn from(value: String) -> Self {
+        Bytes::from(value.into_bytes())
+    }
+}
+
+impl From<&'static [u8]> for Bytes {
+    fn from(value: &'static [u8]) -> Self {
+        Bytes::from_static(value)
+    }
+}
+
+impl From<&'static str> for Bytes {
+    fn from(value: &'static str) -> Self {
+        Bytes::from_static(value.as_bytes())
+    }
+}
+
+impl From<Bytes> for Vec<u8> {
+    fn from(value: Bytes) -> Self {
+        value.into_vec()
+    }
+}
+
+impl PartialEq for Bytes {
+    fn eq(&self, other: &Bytes) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for Bytes {}
+
+impl PartialEq<[u8]> for Bytes {
+    fn eq(&self, other: &[u8]) -> bool {
+        **self == *other
+    }
+}
+
+impl PartialEq<&[u8]> for Bytes {
+    fn eq(&self, other: &&[u8]) -> bool {
+        **self == **other
+    }
+}
+
+impl<const N: usize> PartialEq<&[u8; N]> for Bytes {
+    fn eq(&self, other: &&[u8; N]) -> bool {
+        **self == other[..]
+    }
+}
+
+impl PartialEq<Vec<u8>> for Bytes {
+    fn eq(&self, other: &Vec<u8>) -> bool {
+        **self == **other
+    }
+}
+
+impl PartialEq<str> for Bytes {
+    fn eq(&self, other: &str) -> bool {
+        **self == *other.as_bytes()
+    }
+}
+
+impl PartialEq<&str> for Bytes {
+    fn eq(&self, other: &&str) -> bool {
+        **self == *other.as_bytes()
+    }
+}
+
+impl Hash for Bytes {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state);
+    }
+}
+
+/// Printed like a byte string literal
+impl fmt::Debug for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "b\"{}\"", self.escape_ascii())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slices_share_data() {
+        let mut bytes = Bytes::from(b"hello world".to_vec());
+        let copy = bytes.clone();
+
+        let hello = bytes.split_to(5);
+        assert_eq!(hello, "hello");
+        assert_eq!(bytes, " world");
+        assert_eq!(bytes.slice(1..=3), "wor");
+        assert_eq!(copy.slice(6..), b"world");
+
+        let mut tail = copy.clone();
+        let world = tail.split_off(6);
+        assert_eq!(tail, "hello ");
+        assert_eq!(world, "world");
+
+        // same allocation
+        assert_eq!(hello.as_ptr(), copy.as_ptr());
+
+        bytes.advance(1);
+        bytes.truncate(3);
+        assert_eq!(bytes, "wor");
+        assert_eq!(format!("{bytes:?}"), "b\"wor\"");
+    }
+
+    #[test]
+    fn test_into_vec() {
+        let data = b"data".to_vec();
+        let ptr = data.as_ptr();
+
+        // the only owner gets the allocation back
+        let bytes = Bytes::from(data);
+        let data = bytes.into_vec();
+        assert_eq!(data.as_ptr(), ptr);
+
+        // shared or sliced data is copied
+        let bytes = Bytes::from(b"data".to_vec());
+        let _other = bytes.clone();
+        assert_eq!(bytes.into_vec(), b"data");
+        assert_eq!(Bytes::from("static").slice(1..3).into_vec(), b"ta");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_slice_out_of_bounds() {
+        Bytes::from("abc").slice(2..4);
+    }
+}
@@ -1,46 +1,633 @@
-use std::sync::Arc;
+use std::{
+    any::Any,
+    fmt,
+    future::pending,
+    io::IoSlice,
+    net::{IpAddr, SocketAddr},
+    panic::{AssertUnwindSafe, catch_unwind},
+    pin::{Pin, pin},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    task::{Context, Poll},
+};
 
 use tokio::{
-    io::{AsyncReadExt, ReadHalf, WriteHalf, split},
-    net::TcpStream,
-    time::{Duration, timeout},
+    io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf, split},
+    select,
+    sync::{Mutex, oneshot, watch},
+    time::{Duration, Instant, Sleep, sleep, timeout, timeout_at},
 };
 
 use crate::{
-    body::HttpBody, error::ServerError, request::HttpRequest, response::HttpResponse,
-    router::HttpRouter, utils::find_headers_end, version::HttpVersion,
+    body::{HttpBody, IncomingBody},
+    error::ServerError,
+    forwarded::{ForwardedClient, TrustedProxies},
+    handler::HandlerFn,
+    method::HttpMethod,
+    proxy_protocol::ProxyHeader,
+    request::HttpRequest,
+    response::HttpResponse,
+    router::HttpRouter,
+    server::Timeouts,
+    status::StatusCode,
+    utils::find_headers_end,
+    version::HttpVersion,
 };
 
+/// Interim response for `Expect: 100-continue`
+const CONTINUE_RESPONSE: &[u8] = b"HTTP/1.1 100 Continue\r\n\r\n";
+/// Unread request body larger than this closes the connection instead of being discarded
+const MAX_DISCARD_SIZE: usize = 64 * 1024;
+/// Maximum length of a chunk size line or a trailer line
+const MAX_LINE_SIZE: usize = 4096;
+/// Bytes reserved for every read from the socket
+const READ_SIZE: usize = 8192;
+/// How long the rest of a rejected request is read before closing
+const LINGER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A byte stream a connection can run over, such as a TCP or Unix socket
+pub trait Transport: AsyncRead + AsyncWrite + Send + Sync + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Sync + Unpin> Transport for T {}
+
+pub(crate) type BoxedTransport = Box<dyn Transport>;
+
+/// Metadata of a connection, kept apart from the transport
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    /// Address of the client, if the transport has one
+    pub peer_addr: Option<SocketAddr>,
+    /// Address the connection was accepted on
+    pub local_addr: Option<SocketAddr>,
+    /// Credentials of the client process, on Unix sockets
+    pub peer_cred: Option<PeerCred>,
+    /// Whether the transport is encrypted, set by whoever terminates TLS
+    pub tls: bool,
+    /// Number of the request on the connection, starting at 1, 0 outside of a request
+    pub request_number: usize,
+    /// The client behind trusted proxies, see `TrustedProxies`
+    pub forwarded: Option<ForwardedClient>,
+    /// The PROXY protocol header the connection started with
+    pub proxy_header: Option<Arc<ProxyHeader>>,
+}
+
+impl ConnectionInfo {
+    /// IP of the client, the forwarded one behind a trusted proxy
+    pub fn client_ip(&self) -> Option<IpAddr> {
+        match &self.forwarded {
+            Some(forwarded) => forwarded.ip,
+            None => self.peer_addr.map(|addr| addr.ip()),
+        }
+    }
+
+    /// `https` or `http`, the forwarded one behind a trusted proxy
+    pub fn scheme(&self) -> &str {
+        match self.forwarded.as_ref().and_then(|f| f.proto.as_deref()) {
+            Some(proto) => proto,
+            None if self.tls => "https",
+            None => "http",
+        }
+    }
+}
+
+/// Printed as the client address, or the client process on Unix sockets
+impl fmt::Display for ConnectionInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.peer_addr, self.peer_cred) {
+            (Some(addr), _) => write!(f, "{addr}"),
+            (
+                None,
+                Some(PeerCred {
+                    uid,
+                    pid: Some(pid),
+                    ..
+                }),
+            ) => write!(f, "pid {pid} (uid {uid})"),
+            (None, Some(PeerCred { uid, .. })) => write!(f, "uid {uid}"),
+            (None, None) => f.write_str("unknown peer"),
+        }
+    }
+}
+
+/// Credentials of the process on the other end of a Unix socket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerCred {
+    pub uid: u32,
+    pub gid: u32,
+    /// Not reported on every platform
+    pub pid: Option<i32>,
+}
+
+/// How the request body is delimited
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BodyFraming {
+    /// `Content-Length`, with the remaining bytes
+    Length(usize),
+    /// `Transfer-Encoding: chunked`
+    Chunked(ChunkState),
+    /// no body, or the body was read completely
+    Done,
+    /// reading the body failed, the connection can't be reused
+    Failed,
+}
+
+/// Position inside a chunked body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChunkState {
+    /// waiting for the chunk size line
+    Size,
+    /// remaining bytes of the current chunk
+    Data(usize),
+    /// CRLF after the chunk data
+    DataEnd,
+    /// trailer section after the last chunk
+    Trailers,
+}
+
+/// Read side of the connection, shared with the request body
+pub(crate) struct ConnectionReader {
+    /// Reader half of the transport
+    reader: ReadHalf<BoxedTransport>,
+    /// Bytes read from the socket but not consumed yet, kept between requests
+    buffer: Vec<u8>,
+    /// Framing of the current request body
+    framing: BodyFraming,
+    /// Longest wait for more of the request body
+    body_timeout: Duration,
+    /// Counts the requests, a body can only be read while its request is current
+    request: Arc<AtomicUsize>,
+}
+
+/// The request a body belongs to, it can be checked without locking the reader
+#[derive(Debug, Clone)]
+pub(crate) struct Generation {
+    current: Arc<AtomicUsize>,
+    generation: usize,
+    /// Shared by the clones, to tell if the body still exists
+    users: Arc<()>,
+}
+
+impl Generation {
+    /// Whether the connection is still at this request
+    pub(crate) fn is_current(&self) -> bool {
+        self.current.load(Ordering::Acquire) == self.generation
+    }
+
+    /// Whether another clone is alive, such as the body held by the response
+    fn in_use(&self) -> bool {
+        Arc::strong_count(&self.users) > 1
+    }
+}
+
+impl ConnectionReader {
+    fn new(reader: ReadHalf<BoxedTransport>, body_timeout: Duration) -> Self {
+        ConnectionReader {
+            reader,
+            buffer: Vec::new(),
+            framing: BodyFraming::Done,
+            body_timeout,
+            request: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Start reading the next request
+    fn next_request(&mut self) -> Generation {
+        let generation = self.request.fetch_add(1, Ordering::AcqRel) + 1;
+        Generation {
+            current: self.request.clone(),
+            generation,
+            users: Arc::default(),
+        }
+    }
+
+    /// Read the next chunk of the body of the request `generation`
+    ///
+    /// Fails once the connection moved on to another request, the rest of
+    /// the body was discarded then.
+    pub(crate) async fn read_body_of(
+        &mut self,
+        generation: &Generation,
+    ) -> io::Result<Option<Vec<u8>>> {
+        if !generation.is_current() {
+            return Err(request_over());
+        }
+
+        self.read_body().await
+    }
+
+    /// Read more bytes from the socket, return 0 on EOF
+    async fn fill_buf(&mut self) -> io::Result<usize> {
+        self.buffer.reserve(READ_SIZE);
+        self.reader.read_buf(&mut self.buffer).await
+    }
+
+    /// Same as `fill_buf`, but EOF or no data for the body timeout is an error
+    async fn fill_body_buf(&mut self) -> io::Result<()> {
+        let read = timeout(self.body_timeout, self.fill_buf())
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "request body timeout"))?;
+        match read? {
+            0 => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed while reading body",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Take a CRLF terminated line from the buffer, without the CRLF
+    async fn read_line(&mut self) -> io::Result<Vec<u8>> {
+        loop {
+            if let Some(pos) = self.buffer.windows(2).position(|w| w == b"\r\n") {
+                let line = self.buffer[..pos].to_vec();
+                self.buffer.drain(..pos + 2);
+                return Ok(line);
+            }
+
+            if self.buffer.len() > MAX_LINE_SIZE {
+                return Err(invalid_data("chunk line was too long"));
+            }
+
+            self.fill_body_buf().await?;
+        }
+    }
+
+    /// Take at most `max` bytes from the buffer, read the socket if it is empty
+    async fn read_data(&mut self, max: usize) -> io::Result<Vec<u8>> {
+        if self.buffer.is_empty() {
+            self.fill_body_buf().await?;
+        }
+
+        // hand over the whole buffer if possible, it is only copied for a part
+        if max >= self.buffer.len() {
+            return Ok(std::mem::take(&mut self.buffer));
+        }
+        Ok(self.buffer.drain(..max).collect())
+    }
+
+    /// Read the next chunk of the request body
+    ///
+    /// After an error the body can't be read any further.
+    pub(crate) async fn read_body(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let result = self.next_body_chunk().await;
+        if result.is_err() {
+            self.framing = BodyFraming::Failed;
+        }
+        result
+    }
+
+    async fn next_body_chunk(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            match self.framing {
+                BodyFraming::Failed => {
+                    return Err(io::Error::other("reading the request body failed"));
+                }
+                BodyFraming::Done | BodyFraming::Length(0) => {
+                    self.framing = BodyFraming::Done;
+                    return Ok(None);
+                }
+                BodyFraming::Length(remaining) => {
+                    let data = self.read_data(remaining).await?;
+                    self.framing = BodyFraming::Length(remaining - data.len());
+                    return Ok(Some(data));
+                }
+                BodyFraming::Chunked(ChunkState::Size) => {
+                    let size = parse_chunk_size(&self.read_line().await?)?;
+                    self.framing = match size {
+                        0 => BodyFraming::Chunked(ChunkState::Trailers),
+                        n => BodyFraming::Chunked(ChunkState::Data(n)),
+                    };
+                }
+                BodyFraming::Chunked(ChunkState::Data(remaining)) => {
+                    let data = self.read_data(remaining).await?;
+                    self.framing = match remaining - data.len() {
+                        0 => BodyFraming::Chunked(ChunkState::DataEnd),
+                        n => BodyFraming::Chunked(ChunkState::Data(n)),
+                    };
+                    return Ok(Some(data));
+                }
+                BodyFraming::Chunked(ChunkState::DataEnd) => {
+                    if !self.read_line().await?.is_empty() {
+                        return Err(invalid_data("missing CRLF after chunk data"));
+                    }
+                    self.framing = BodyFraming::Chunked(ChunkState::Size);
+                }
+                BodyFraming::Chunked(ChunkState::Trailers) => {
+                    // trailer fields are not exposed, skip them
+                    if self.read_line().await?.is_empty() {
+                        self.framing = BodyFraming::Done;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Discard the rest of the request body
+    ///
+    /// Return `false` if more than `limit` bytes would have to be read
+    async fn discard_body(&mut self, limit: usize) -> io::Result<bool> {
+        // the body is gone for the handler, even if it kept it
+        self.next_request();
+
+        if let BodyFraming::Length(remaining) = self.framing
+            && remaining > limit
+        {
+            return Ok(false);
+        }
+
+        let mut discarded = 0;
+        while let Some(chunk) = self.read_body().await? {
+            discarded += chunk.len();
+            if discarded > limit {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Error of a body read after its request is over
+pub(crate) fn request_over() -> io::Error {
+    io::Error::other("the request is over, the rest of its body was discarded")
+}
+
+/// Parse the chunk size line, chunk extensions are ignored
+fn parse_chunk_size(line: &[u8]) -> io::Result<usize> {
+    let line = std::str::from_utf8(line).map_err(|_| invalid_data("invalid chunk size"))?;
+    let size = line.split(';').next().unwrap_or("").trim();
+
+    if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(invalid_data("invalid chunk size"));
+    }
+
+    usize::from_str_radix(size, 16).map_err(|_| invalid_data("chunk size was too big"))
+}
+
+/// Find out how the body of the request is delimited
+fn body_framing(request: &HttpRequest) -> Result<BodyFraming, ServerError> {
+    let encodings = request.headers.get_list("Transfer-Encoding");
+    // a proxy framing by the length would find another next request, see RFC 9112 6.3
+    if !encodings.is_empty() && request.headers.contains_key("Content-Length") {
+        return Err(ServerError::ProtocolError(
+            "both Transfer-Encoding and Content-Length".to_string(),
+        ));
+    }
+    if let Some(last) = encodings.last() {
+        // chunked must be the last transfer coding
+        if !last.eq_ignore_ascii_case("chunked") {
+            return Err(ServerError::ProtocolError(format!(
+                "unsupported transfer encoding: {}",
+                encodings.join(", ")
+            )));
+        }
+
+        return Ok(BodyFraming::Chunked(ChunkState::Size));
+    }
+
+    // repeated lengths are only accepted if they are all the same
+    let lengths = request.headers.get_list("Content-Length");
+    let Some(length) = lengths.first() else {
+        return Ok(BodyFraming::Done);
+    };
+    if lengths.iter().any(|other| other != length) {
+        return Err(ServerError::ProtocolError(format!(
+            "conflicting content lengths: {}",
+            lengths.join(", ")
+        )));
+    }
+
+    match length.parse::<usize>() {
+        Ok(0) => Ok(BodyFraming::Done),
+        Ok(n) => Ok(BodyFraming::Length(n)),
+        Err(_) => Err(ServerError::ProtocolError(format!(
+            "invalid content length: {length}"
+        ))),
+    }
+}
+
+/// Wait until the request body asks for `100 Continue`, or forever if it can't
+async fn continue_requested(rx: &mut Option<oneshot::Receiver<()>>) -> bool {
+    match rx {
+        Some(rx) => rx.await.is_ok(),
+        None => pending().await,
+    }
+}
+
+/// Writer which fails with `TimedOut` when a write makes no progress for `timeout`
+struct WriteTimeout<W> {
+    inner: W,
+    timeout: Duration,
+    /// Started when a write is pending
+    timer: Option<Pin<Box<Sleep>>>,
+}
+
+impl<W> WriteTimeout<W> {
+    fn new(inner: W, timeout: Duration) -> Self {
+        WriteTimeout {
+            inner,
+            timeout,
+            timer: None,
+        }
+    }
+
+    /// Pass on a ready result, otherwise check the timer
+    fn poll_timeout<T>(
+        &mut self,
+        cx: &mut Context<'_>,
+        poll: Poll<io::Result<T>>,
+    ) -> Poll<io::Result<T>> {
+        if poll.is_ready() {
+            self.timer = None;
+            return poll;
+        }
+
+        let timeout = self.timeout;
+        let timer = self.timer.get_or_insert_with(|| Box::pin(sleep(timeout)));
+        match timer.as_mut().poll(cx) {
+            Poll::Ready(()) => {
+                self.timer = None;
+                Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "response write timeout",
+                )))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for WriteTimeout<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        self.poll_timeout(cx, poll)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write_vectored(cx, bufs);
+        self.poll_timeout(cx, poll)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let poll = Pin::new(&mut self.inner).poll_flush(cx);
+        self.poll_timeout(cx, poll)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let poll = Pin::new(&mut self.inner).poll_shutdown(cx);
+        self.poll_timeout(cx, poll)
+    }
+}
+
+/// Future which turns a panic while polling `F` into an error
+struct CatchUnwind<F>(F);
+
+impl<F: Future + Unpin> Future for CatchUnwind<F> {
+    type Output = Result<F::Output, Box<dyn Any + Send>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // the future is not polled again after a panic
+        match catch_unwind(AssertUnwindSafe(|| Pin::new(&mut self.0).poll(cx))) {
+            Ok(poll) => poll.map(Ok),
+            Err(panic) => Poll::Ready(Err(panic)),
+        }
+    }
+}
+
+/// The message of a panic payload, if it has one
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => panic
+            .downcast_ref::<String>()
+            .map(String::as_str)
+            .unwrap_or("Box<dyn Any>"),
+    }
+}
+
+/// Wait until the connection fails on the read side, or forever if it can't be watched
+///
+/// The reader is only watched once the request body is done, what the client
+/// sends meanwhile stays buffered for the next request. EOF only ends the
+/// watch, a client which shut down its writing side still reads the response.
+async fn client_closed(reader: &Mutex<ConnectionReader>) {
+    let mut reader = reader.lock().await;
+    if reader.framing == BodyFraming::Done {
+        while reader.buffer.len() < MAX_DISCARD_SIZE {
+            match reader.fill_buf().await {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(_) => return,
+            }
+        }
+    }
+    drop(reader);
+    pending().await
+}
+
+/// Wait until the server shuts down, or forever without a shutdown signal
+async fn shutdown_requested(shutdown: &mut Option<watch::Receiver<bool>>) {
+    if let Some(rx) = shutdown
+        && rx.wait_for(|&shutdown| shutdown).await.is_ok()
+    {
+        return;
+    }
+    pending().await
+}
+
 pub struct HttpConnection {
-    /// Reader half of the TCP stream
-    reader: ReadHalf<TcpStream>,
-    /// Writer half of the TCP stream
-    writer: WriteHalf<TcpStream>,
+    /// Reader side of the transport, shared with the request body
+    reader: Arc<Mutex<ConnectionReader>>,
+    /// Writer half of the transport
+    writer: WriteTimeout<WriteHalf<BoxedTransport>>,
+    /// Addresses of the connection
+    info: ConnectionInfo,
+    /// Response heads are serialized here, reused across requests
+    write_buf: Vec<u8>,
     /// Router
     router: Arc<HttpRouter>,
-    /// Timeout for each connection
-    timeout: Duration,
-    /// Buffer size for reading
+    /// Timeouts of reading the request and writing the response
+    timeouts: Timeouts,
+    /// Close the connection after this many requests
+    max_requests: Option<usize>,
+    /// Maximum size of the request line and headers
     buffer_size: usize,
     /// Whether to keep the connection alive
     keep_alive: bool,
+    /// Set when the server shuts down
+    shutdown: Option<watch::Receiver<bool>>,
+    /// Panics of handlers and response bodies, shared with the server
+    panics: Arc<AtomicUsize>,
+    /// Peers whose forwarding headers name the client
+    trusted_proxies: Option<Arc<TrustedProxies>>,
+    /// Whether the connection starts with a PROXY protocol header
+    proxy_protocol: bool,
 }
 
 impl HttpConnection {
-    pub fn new(stream: TcpStream, router: HttpRouter, timeout_secs: u64) -> Self {
+    /// Serve HTTP over `stream`, any transport such as a `TcpStream` or a `DuplexStream`
+    pub fn new<S>(stream: S, router: HttpRouter, timeouts: Timeouts) -> Self
+    where
+        S: Transport + 'static,
+    {
+        HttpConnection::from_transport(Box::new(stream), router, timeouts)
+    }
+
+    /// Like `new`, for a transport which is boxed already
+    pub(crate) fn from_transport(
+        stream: BoxedTransport,
+        router: HttpRouter,
+        timeouts: Timeouts,
+    ) -> Self {
         // split the stream into reader and writer
         let (reader, writer) = split(stream);
 
         HttpConnection {
-            reader,
-            writer,
+            reader: Arc::new(Mutex::new(ConnectionReader::new(reader, timeouts.body))),
+            writer: WriteTimeout::new(writer, timeouts.write),
+            info: ConnectionInfo::default(),
+            write_buf: Vec::with_capacity(READ_SIZE),
             router: Arc::new(router),
-            timeout: Duration::from_secs(timeout_secs),
+            timeouts,
+            max_requests: None,
             buffer_size: 8192,
             keep_alive: true,
+            shutdown: None,
+            panics: Arc::default(),
+            trusted_proxies: None,
+            proxy_protocol: false,
         }
     }
 
+    /// Set the addresses of the connection, the transport doesn't provide them
+    ///
+    /// Every request carries a copy in `HttpRequest::connection`.
+    pub fn set_info(&mut self, info: ConnectionInfo) {
+        self.info = info;
+    }
+
+    pub fn info(&self) -> &ConnectionInfo {
+        &self.info
+    }
+
     pub fn keep_alive(&mut self, keep_alive: bool) {
         self.keep_alive = keep_alive;
     }
@@ -49,97 +636,423 @@ impl HttpConnection {
         self.buffer_size = size;
     }
 
+    /// Close the connection after `max` requests, `None` for no limit
+    pub fn max_requests(&mut self, max: Option<usize>) {
+        self.max_requests = max;
+    }
+
+    /// Resolve the client from forwarding headers sent by these proxies
+    pub fn trusted_proxies(&mut self, proxies: Option<Arc<TrustedProxies>>) {
+        self.trusted_proxies = proxies;
+    }
+
+    /// Require a PROXY protocol header, v1 or v2, before the first request
+    ///
+    /// The addresses in the header replace the ones of the transport. A
+    /// connection without a valid header is closed.
+    pub fn proxy_protocol(&mut self, required: bool) {
+        self.proxy_protocol = required;
+    }
+
+    /// Close the connection once idle after `shutdown` is set
+    pub(crate) fn shutdown_signal(&mut self, shutdown: watch::Receiver<bool>) {
+        self.shutdown = Some(shutdown);
+    }
+
+    /// Count panics in `panics`, instead of a counter of this connection
+    pub(crate) fn panic_counter(&mut self, panics: Arc<AtomicUsize>) {
+        self.panics = panics;
+    }
+
+    /// Number of handlers and response bodies which panicked
+    pub fn panic_count(&self) -> usize {
+        self.panics.load(Ordering::Relaxed)
+    }
+
+    fn is_shutting_down(&self) -> bool {
+        self.shutdown.as_ref().is_some_and(|rx| *rx.borrow())
+    }
+
+    /// Take the PROXY protocol header from the start of the connection
+    async fn read_proxy_header(&mut self) -> Result<(), ServerError> {
+        let deadline = Instant::now() + self.timeouts.first_byte;
+        let mut reader = self.reader.lock().await;
+        let (header, length) = loop {
+            if let Some(parsed) = ProxyHeader::parse(&reader.buffer)? {
+                break parsed;
+            }
+
+            match timeout_at(deadline, reader.fill_buf()).await {
+                Ok(Ok(0)) => {
+                    return Err(ServerError::ProtocolError(
+                        "connection closed before the PROXY protocol header".to_string(),
+                    ));
+                }
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => return Err(ServerError::IOError(e)),
+                Err(_) => {
+                    return Err(ServerError::TimeoutError(
+                        "no PROXY protocol header was sent".to_string(),
+                    ));
+                }
+            }
+        };
+        reader.buffer.drain(..length);
+
+        // `LOCAL` and `UNKNOWN` keep the addresses of the transport
+        if let Some(source) = header.source {
+            self.info.peer_addr = Some(source);
+            self.info.local_addr = header.destination;
+        }
+        self.info.tls |= header.is_tls();
+        self.info.proxy_header = Some(Arc::new(header));
+
+        Ok(())
+    }
+
+    /// Answer a request the connection can't go on with, then close it
+    ///
+    /// The response comes from the router's error handler for `status`.
+    async fn reject(
+        &mut self,
+        status: StatusCode,
+        method: HttpMethod,
+        error: ServerError,
+    ) -> Result<(), ServerError> {
+        let mut response = self.router.error_response(status);
+        response.headers_mut().insert("Connection", "close");
+
+        // the client may be gone already, the error is what gets reported
+        if response
+            .send_with_buffer(&mut self.writer, method, &mut self.write_buf)
+            .await
+            .is_ok()
+        {
+            self.linger().await;
+        }
+        Err(error)
+    }
+
+    /// Close the writing side and read what the client still sends
+    ///
+    /// Closing with unread data resets the connection, which can discard the
+    /// response before the client read it.
+    async fn linger(&mut self) {
+        if self.writer.shutdown().await.is_err() {
+            return;
+        }
+
+        let mut reader = self.reader.lock().await;
+        let _ = timeout(LINGER_TIMEOUT, async {
+            let mut discarded = 0;
+            while discarded < MAX_DISCARD_SIZE {
+                reader.buffer.clear();
+                match reader.fill_buf().await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => discarded += n,
+                }
+            }
+        })
+        .await;
+    }
+
     /// Process the connection
+    ///
+    /// Waiting for the first request or between requests ends with a silent
+    /// close, a request head which is not complete in time gets a `408`.
+    /// Requests which can't be parsed get an error response before the
+    /// connection is closed, a missing route keeps it open.
     pub async fn process(&mut self) -> Result<(), ServerError> {
+        if self.proxy_protocol {
+            self.read_proxy_header().await?;
+        }
+
+        let mut served = 0;
+
         // keep-alive loop, process multiple requests
         loop {
-            let mut buffer = vec![0; self.buffer_size];
-            let mut read_bytes_for_headers = 0;
-
-            // read the request headers
-            loop {
-                match timeout(
-                    self.timeout,
-                    self.reader.read(&mut buffer[read_bytes_for_headers..]),
-                )
-                .await
-                {
-                    // connect closed by peer
-                    Ok(Ok(0)) => return Ok(()),
-                    Ok(Ok(n)) => {
-                        read_bytes_for_headers += n;
-
-                        // if find the headers it is complete
-                        if let Some(_pos) = find_headers_end(&buffer[..read_bytes_for_headers]) {
-                            break;
-                        }
+            // read the request headers, bytes of the next requests stay in the buffer
+            let mut head_deadline = None;
+            let head = {
+                let mut reader = self.reader.lock().await;
+                loop {
+                    // empty lines before the request line are ignored
+                    while reader.buffer.starts_with(b"\r\n") {
+                        reader.buffer.drain(..2);
+                    }
+
+                    // if find the headers it is complete
+                    let end = find_headers_end(&reader.buffer);
+                    let too_big = match end {
+                        Some(pos) => pos > self.buffer_size,
+                        None => reader.buffer.len() >= self.buffer_size,
+                    };
+                    if too_big {
+                        drop(reader);
+                        return self
+                            .reject(
+                                StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+                                HttpMethod::Get,
+                                ServerError::ProtocolError(
+                                    "request header was too big".to_string(),
+                                ),
+                            )
+                            .await;
+                    }
+                    if let Some(pos) = end {
+                        break reader.buffer.drain(..pos).collect::<Vec<u8>>();
+                    }
+
+                    // the head must be complete in time once it started
+                    let idle = reader.buffer.is_empty();
+                    let deadline = match (idle, served) {
+                        (true, 0) => Instant::now() + self.timeouts.first_byte,
+                        (true, _) => Instant::now() + self.timeouts.idle,
+                        (false, _) => *head_deadline
+                            .get_or_insert_with(|| Instant::now() + self.timeouts.headers),
+                    };
 
-                        if read_bytes_for_headers >= buffer.len() {
-                            return Err(ServerError::ProtocolError(
-                                "request header was too big".to_string(),
+                    // an idle connection is closed right away on shutdown
+                    let read = timeout_at(deadline, reader.fill_buf());
+                    let result = match idle {
+                        true => select! {
+                            biased;
+                            _ = shutdown_requested(&mut self.shutdown) => return Ok(()),
+                            result = read => result,
+                        },
+                        false => read.await,
+                    };
+
+                    match result {
+                        // connect closed by peer
+                        Ok(Ok(0)) => return Ok(()),
+                        Ok(Ok(_)) => {}
+                        Ok(Err(e)) => return Err(ServerError::IOError(e)),
+                        Err(_) if idle && served > 0 => return Ok(()),
+                        Err(_) if idle => {
+                            return Err(ServerError::TimeoutError(
+                                "no request was sent".to_string(),
                             ));
                         }
+                        Err(_) => {
+                            drop(reader);
+                            return self
+                                .reject(
+                                    StatusCode::REQUEST_TIMEOUT,
+                                    HttpMethod::Get,
+                                    ServerError::TimeoutError("request header timeout".to_string()),
+                                )
+                                .await;
+                        }
                     }
-                    Ok(Err(e)) => return Err(ServerError::IOError(e)),
-                    Err(_) => return Err(ServerError::TimeoutError("request timeout".to_string())),
                 }
-            }
+            };
+            served += 1;
 
             // process the headers
-            let request_str =
-                String::from_utf8_lossy(&buffer[..read_bytes_for_headers]).to_string();
-            let request = HttpRequest::from(request_str);
+            let request_str = String::from_utf8_lossy(&head).to_string();
+            let mut request = match HttpRequest::from_head(&request_str) {
+                Ok(mut request) => {
+                    request.connection = ConnectionInfo {
+                        request_number: served,
+                        forwarded: self
+                            .trusted_proxies
+                            .as_ref()
+                            .and_then(|proxies| proxies.resolve(&self.info, &request.headers)),
+                        ..self.info.clone()
+                    };
+                    request
+                }
+                Err(e) => {
+                    return self
+                        .reject(StatusCode::BAD_REQUEST, HttpMethod::Get, e)
+                        .await;
+                }
+            };
+            if !request.version.is_supported() {
+                let error = ServerError::ProtocolError("unsupported HTTP version".to_string());
+                return self
+                    .reject(
+                        StatusCode::HTTP_VERSION_NOT_SUPPORTED,
+                        request.method,
+                        error,
+                    )
+                    .await;
+            }
+            // without the framing the next request can't be found
+            let framing = match body_framing(&request) {
+                Ok(framing) => framing,
+                Err(e) => {
+                    return self
+                        .reject(StatusCode::BAD_REQUEST, request.method, e)
+                        .await;
+                }
+            };
 
             // check if the request is need keep-alive
             let mut connection_keep_alive;
             if request.version == HttpVersion::V1_1 {
-                connection_keep_alive = !request
-                    .headers
-                    .get("Connection")
-                    .is_some_and(|h| h.eq_ignore_ascii_case("close"));
+                connection_keep_alive = !request.headers.has_token("Connection", "close");
             } else {
-                connection_keep_alive = request
-                    .headers
-                    .get("Connection")
-                    .is_some_and(|h| h.eq_ignore_ascii_case("keep-alive"));
+                connection_keep_alive = request.headers.has_token("Connection", "keep-alive");
             }
             if !self.keep_alive {
                 connection_keep_alive = false;
             }
 
-            // find the handler
-            let handler = self
-                .router
-                .find_handler(&request.uri.path, request.method)
-                .await;
-            let mut response = match handler {
-                Some(h) => h(request).await,
-                None => {
-                    // if the handler is not found, return 404
-                    let mut response = HttpResponse::new(404, "Not Found");
-                    response.headers_mut().insert("Content-Type", "text/plain");
-                    response.add_body(HttpBody::from("Not Found"));
+            // `100-continue` is the only expectation defined
+            let expect_continue = match request.headers.get("Expect") {
+                Some(expect) if expect.eq_ignore_ascii_case("100-continue") => {
+                    framing != BodyFraming::Done && request.version == HttpVersion::V1_1
+                }
+                Some(_) => {
+                    let mut response = self.router.error_response(StatusCode::EXPECTATION_FAILED);
+                    response.headers_mut().insert("Connection", "close");
 
                     response
-                        .send(&mut self.writer)
+                        .send(&mut self.writer, request.method)
                         .await
                         .map_err(ServerError::IOError)?;
+                    self.linger().await;
 
                     return Ok(());
                 }
+                None => false,
+            };
+
+            // a body kept from an earlier request can't read into this one
+            let generation = {
+                let mut reader = self.reader.lock().await;
+                reader.framing = framing;
+                reader.next_request()
+            };
+
+            // attach the body, it is read from the connection on demand
+            let mut continue_rx = None;
+            if framing != BodyFraming::Done {
+                let continue_tx = match expect_continue {
+                    true => {
+                        let (tx, rx) = oneshot::channel();
+                        continue_rx = Some(rx);
+                        Some(tx)
+                    }
+                    false => None,
+                };
+                let content_length = match framing {
+                    BodyFraming::Length(n) => Some(n),
+                    _ => None,
+                };
+
+                request.body = Some(HttpBody::Incoming(IncomingBody::new(
+                    self.reader.clone(),
+                    generation.clone(),
+                    continue_tx,
+                    content_length,
+                )));
+            }
+
+            // find the handler, routes are matched against the decoded path
+            // `HEAD` is answered by the `GET` handler if it has no own route
+            let method = request.method;
+            let path = request.uri.decoded_path();
+            let mut handler = self.router.find_handler(&path, method).await;
+            if handler.is_none() && method == HttpMethod::Head {
+                handler = self.router.find_handler(&path, HttpMethod::Get).await;
+            }
+            let mut panicked = false;
+            let (mut response, continue_sent) = match handler {
+                Some(h) => match self.call_handler(h, request, continue_rx).await? {
+                    (Some(response), continue_sent) => (response, continue_sent),
+                    (None, continue_sent) => {
+                        panicked = true;
+                        let response = self
+                            .router
+                            .error_response(StatusCode::INTERNAL_SERVER_ERROR);
+                        (response, continue_sent)
+                    }
+                },
+                // the body is discarded as usual, the connection stays open
+                None if method == HttpMethod::NoSupport => (
+                    self.router.error_response(StatusCode::NOT_IMPLEMENTED),
+                    false,
+                ),
+                None => (self.router.error_response(StatusCode::NOT_FOUND), false),
             };
 
+            // the client is still waiting for `100 Continue` and may never send the body
+            let framing = self.reader.lock().await.framing;
+            let body_done = framing == BodyFraming::Done;
+            if !body_done && expect_continue && !continue_sent || framing == BodyFraming::Failed {
+                connection_keep_alive = false;
+            }
+
+            // finish this response, but don't wait for another one
+            let limit_reached = self.max_requests.is_some_and(|max| served >= max);
+            if panicked || limit_reached || self.is_shutting_down() {
+                connection_keep_alive = false;
+            }
+
+            // skip the body the handler didn't read, so the next request can be parsed,
+            // a body too big to skip closes the connection and the response says so
+            // a body still held, such as by the response, is skipped once that is sent
+            let body_in_use = !body_done && generation.in_use();
+            let mut undrained = false;
+            if !body_done && !body_in_use && connection_keep_alive {
+                let mut reader = self.reader.lock().await;
+                if !matches!(reader.discard_body(MAX_DISCARD_SIZE).await, Ok(true)) {
+                    connection_keep_alive = false;
+                    undrained = true;
+                }
+            }
+
             if connection_keep_alive && self.keep_alive {
                 response.headers_mut().insert("Connection", "keep-alive");
             } else {
                 response.headers_mut().insert("Connection", "close");
             }
 
-            response
-                .send(&mut self.writer)
-                .await
-                .map_err(ServerError::IOError)?;
+            // a client gone in the middle of a stream drops the body, so its sender notices
+            let send = response.send_with_buffer(&mut self.writer, method, &mut self.write_buf);
+            let sent = select! {
+                biased;
+                sent = CatchUnwind(pin!(send)) => sent,
+                _ = client_closed(&self.reader) => return Ok(()),
+            };
+            let sent = match sent {
+                Ok(sent) => sent,
+                // part of the response may be written, the connection can't be used anymore
+                Err(panic) => {
+                    self.panics.fetch_add(1, Ordering::Relaxed);
+                    let message = panic_message(&*panic);
+                    eprintln!("Response body panicked on {method:?} {path}: {message}");
+                    return Err(ServerError::InternalError(format!(
+                        "response body panicked: {message}"
+                    )));
+                }
+            };
+            match sent {
+                Ok(()) => {}
+                // the response was refused before anything was written
+                Err(e) if e.kind() == io::ErrorKind::InvalidInput => {
+                    let error = ServerError::IOError(e);
+                    return self
+                        .reject(StatusCode::INTERNAL_SERVER_ERROR, method, error)
+                        .await;
+                }
+                Err(e) => return Err(ServerError::IOError(e)),
+            }
+
+            if body_in_use && connection_keep_alive {
+                let mut reader = self.reader.lock().await;
+                if !matches!(reader.discard_body(MAX_DISCARD_SIZE).await, Ok(true)) {
+                    connection_keep_alive = false;
+                    undrained = true;
+                }
+            }
 
+            if undrained {
+                self.linger().await;
+            }
             if !connection_keep_alive {
                 break;
             }
@@ -147,4 +1060,963 @@ impl HttpConnection {
 
         Ok(())
     }
+
+    /// Run the handler, sending `100 Continue` when it starts reading the body
+    ///
+    /// Return the response, `None` if the handler panicked, and whether
+    /// `100 Continue` was sent
+    async fn call_handler(
+        &mut self,
+        handler: HandlerFn,
+        request: HttpRequest,
+        mut continue_rx: Option<oneshot::Receiver<()>>,
+    ) -> Result<(Option<HttpResponse>, bool), ServerError> {
+        let route = format!("{:?} {}", request.method, request.uri.path);
+        let mut future = CatchUnwind(handler(request));
+        let mut continue_sent = false;
+
+        loop {
+            select! {
+                result = &mut future => {
+                    let response = match result {
+                        Ok(response) => Some(response),
+                        Err(panic) => {
+                            self.panics.fetch_add(1, Ordering::Relaxed);
+                            eprintln!("Handler panicked on {route}: {}", panic_message(&*panic));
+                            None
+                        }
+                    };
+                    return Ok((response, continue_sent));
+                }
+                requested = continue_requested(&mut continue_rx) => {
+                    continue_rx = None;
+
+                    if requested {
+                        self.writer.write_all(CONTINUE_RESPONSE).await?;
+                        self.writer.flush().await?;
+                        continue_sent = true;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::*;
+    use crate::feature::Sse;
+    use tokio::{
+        io::{AsyncBufRead, AsyncBufReadExt, BufReader},
+        net::{TcpListener, TcpStream},
+        test,
+    };
+
+    /// Serve a single connection, return the address to connect to
+    async fn serve(router: HttpRouter) -> SocketAddr {
+        serve_with(router, Timeouts::default(), |_| {}).await
+    }
+
+    /// Same as `serve`, with other timeouts and settings
+    async fn serve_with(
+        router: HttpRouter,
+        timeouts: Timeouts,
+        configure: impl FnOnce(&mut HttpConnection) + Send + 'static,
+    ) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut connection = HttpConnection::new(socket, router, timeouts);
+            configure(&mut connection);
+            let _ = connection.process().await;
+        });
+
+        addr
+    }
+
+    fn short_timeouts() -> Timeouts {
+        let timeout = Duration::from_millis(100);
+        Timeouts {
+            first_byte: timeout,
+            headers: timeout,
+            body: timeout,
+            idle: timeout,
+            write: timeout,
+        }
+    }
+
+    async fn echo(req: HttpRequest) -> HttpResponse {
+        let mut body = req.body.unwrap();
+        let mut data = Vec::new();
+        while let Some(chunk) = body.read_next().await.unwrap() {
+            data.extend_from_slice(&chunk);
+        }
+
+        HttpResponse::new(StatusCode::OK).with_body(HttpBody::from(data))
+    }
+
+    async fn path(req: HttpRequest) -> HttpResponse {
+        HttpResponse::new(StatusCode::OK).with_body(HttpBody::from(req.uri.path.as_str()))
+    }
+
+    async fn ignore_body(_req: HttpRequest) -> HttpResponse {
+        HttpResponse::new(StatusCode::OK).with_body(HttpBody::from("ignored"))
+    }
+
+    async fn test_router() -> HttpRouter {
+        HttpRouter::new()
+            .add(
+                HttpMethod::Post,
+                "/echo",
+                Arc::new(|req| Box::pin(echo(req))),
+            )
+            .await
+            .add(
+                HttpMethod::Post,
+                "/ignore",
+                Arc::new(|req| Box::pin(ignore_body(req))),
+            )
+            .await
+            .get("/first", path)
+            .await
+            .get("/second", path)
+            .await
+    }
+
+    /// Read one response which has a `Content-Length`, the rest stays buffered
+    async fn read_response(stream: &mut (impl AsyncBufRead + Unpin)) -> String {
+        read_response_with(stream, true).await
+    }
+
+    /// Read one response, the body is only read if `has_body`
+    async fn read_response_with(
+        stream: &mut (impl AsyncBufRead + Unpin),
+        has_body: bool,
+    ) -> String {
+        let mut response = String::new();
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            stream.read_line(&mut line).await.unwrap();
+            assert!(
+                !line.is_empty(),
+                "connection closed before the response was complete"
+            );
+
+            if let Some(n) = line.strip_prefix("Content-Length: ") {
+                length = n.trim().parse::<usize>().unwrap();
+            }
+            response.push_str(&line);
+
+            if line == "\r\n" {
+                break;
+            }
+        }
+
+        if !has_body {
+            return response;
+        }
+
+        let mut body = vec![0u8; length];
+        stream.read_exact(&mut body).await.unwrap();
+        response.push_str(&String::from_utf8_lossy(&body));
+
+        response
+    }
+
+    #[test]
+    async fn test_expect_continue_sent_on_body_read() {
+        let addr = serve(test_router().await).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        stream
+            .write_all(
+                b"POST /echo HTTP/1.1\r\n\
+                  Content-Length: 5\r\n\
+                  Expect: 100-continue\r\n\
+                  Connection: close\r\n\
+                  \r\n",
+            )
+            .await
+            .unwrap();
+
+        let mut interim = vec![0u8; CONTINUE_RESPONSE.len()];
+        stream.read_exact(&mut interim).await.unwrap();
+        assert_eq!(interim, CONTINUE_RESPONSE);
+
+        stream.write_all(b"hello").await.unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("\r\n\r\nhello"));
+    }
+
+    #[test]
+    async fn test_expect_continue_skipped_without_body_read() {
+        let addr = serve(test_router().await).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        stream
+            .write_all(
+                b"POST /ignore HTTP/1.1\r\n\
+                  Content-Length: 5\r\n\
+                  Expect: 100-continue\r\n\
+                  \r\n",
+            )
+            .await
+            .unwrap();
+
+        // the body is never sent, the server has to close the connection
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(!response.contains("100 Continue"));
+        assert!(response.contains("Connection: close"));
+        assert!(response.ends_with("ignored"));
+    }
+
+    #[test]
+    async fn test_unknown_expectation() {
+        let addr = serve(test_router().await).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        stream
+            .write_all(
+                b"POST /echo HTTP/1.1\r\n\
+                  Content-Length: 5\r\n\
+                  Expect: something-else\r\n\
+                  \r\n",
+            )
+            .await
+            .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 417 Expectation Failed"));
+    }
+
+    #[test]
+    async fn test_chunked_request_body() {
+        let addr = serve(test_router().await).await;
+        let mut stream = BufReader::new(TcpStream::connect(addr).await.unwrap());
+
+        stream
+            .write_all(
+                b"POST /echo HTTP/1.1\r\n\
+                  Transfer-Encoding: chunked\r\n\
+                  \r\n\
+                  5;ext=1\r\nhello\r\n\
+                  6\r\n world\r\n\
+                  0\r\n\
+                  Trailer: value\r\n\
+                  \r\n",
+            )
+            .await
+            .unwrap();
+
+        let response = read_response(&mut stream).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("\r\n\r\nhello world"));
+    }
+
+    #[test]
+    async fn test_unread_body_is_discarded() {
+        let addr = serve(test_router().await).await;
+        let mut stream = BufReader::new(TcpStream::connect(addr).await.unwrap());
+
+        stream
+            .write_all(
+                b"POST /ignore HTTP/1.1\r\n\
+                  Content-Length: 11\r\n\
+                  \r\n\
+                  not read...",
+            )
+            .await
+            .unwrap();
+        let response = read_response(&mut stream).await;
+        assert!(response.contains("Connection: keep-alive"));
+        assert!(response.ends_with("ignored"));
+
+        // the connection is still usable
+        stream
+            .write_all(
+                b"POST /echo HTTP/1.1\r\n\
+                  Content-Length: 4\r\n\
+                  \r\n\
+                  next",
+            )
+            .await
+            .unwrap();
+        let response = read_response(&mut stream).await;
+        assert!(response.ends_with("\r\n\r\nnext"));
+    }
+
+    #[test]
+    async fn test_large_unread_body_closes_connection() {
+        let addr = serve(test_router().await).await;
+        let (reader, mut writer) = TcpStream::connect(addr).await.unwrap().into_split();
+        let mut reader = BufReader::new(reader);
+
+        // too big to skip, the pipelined request behind it is not served
+        let body = vec![b'a'; 100 * 1024];
+        let writing = tokio::spawn(async move {
+            let head = format!(
+                "POST /ignore HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+                body.len()
+            );
+            writer.write_all(head.as_bytes()).await?;
+            writer.write_all(&body).await?;
+            writer.write_all(b"GET /first HTTP/1.1\r\n\r\n").await
+        });
+
+        let response = read_response(&mut reader).await;
+        assert!(response.contains("Connection: close\r\n"));
+        assert!(response.ends_with("ignored"));
+
+        // nothing follows the response
+        let mut rest = Vec::new();
+        let _ = reader.read_to_end(&mut rest).await;
+        assert!(rest.is_empty());
+        let _ = writing.await.unwrap();
+    }
+
+    #[test]
+    async fn test_response_streams_request_body() {
+        let router = HttpRouter::new()
+            .add(
+                HttpMethod::Post,
+                "/pipe",
+                Arc::new(|mut req: HttpRequest| {
+                    Box::pin(async move {
+                        HttpResponse::new(StatusCode::OK).with_body(req.body.take().unwrap())
+                    })
+                }),
+            )
+            .await
+            .get("/first", path)
+            .await;
+        let addr = serve(router).await;
+        let (reader, mut writer) = TcpStream::connect(addr).await.unwrap().into_split();
+        let mut reader = BufReader::new(reader);
+
+        // the body is not skipped before the response read it, even if too big to skip
+        let large = vec![b'a'; MAX_DISCARD_SIZE + 1];
+        let sent = large.clone();
+        let writing = tokio::spawn(async move {
+            writer
+                .write_all(b"POST /pipe HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello")
+                .await?;
+            let head = format!(
+                "POST /pipe HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+                sent.len()
+            );
+            writer.write_all(head.as_bytes()).await?;
+            writer.write_all(&sent).await?;
+            writer.write_all(b"GET /first HTTP/1.1\r\n\r\n").await?;
+            Ok::<_, io::Error>(writer)
+        });
+
+        let response = read_response(&mut reader).await;
+        assert!(response.contains("Connection: keep-alive\r\n"));
+        assert!(response.ends_with("\r\n\r\nhello"));
+
+        let response = read_response(&mut reader).await;
+        assert!(response.contains("Connection: keep-alive\r\n"));
+        assert!(response.ends_with(std::str::from_utf8(&large).unwrap()));
+
+        assert!(read_response(&mut reader).await.ends_with("/first"));
+        let _writer = writing.await.unwrap().unwrap();
+    }
+
+    #[test]
+    async fn test_body_kept_after_the_request_fails() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let router = test_router()
+            .await
+            .add(
+                HttpMethod::Post,
+                "/keep",
+                Arc::new(move |req: HttpRequest| {
+                    let tx = tx.clone();
+                    Box::pin(async move {
+                        // read the body after the response was sent
+                        tokio::spawn(async move {
+                            tokio::time::sleep(Duration::from_millis(100)).await;
+                            let mut body = req.body.unwrap();
+                            let _ = tx.send(body.read_next().await);
+                        });
+                        HttpResponse::text("kept")
+                    })
+                }),
+            )
+            .await;
+        let addr = serve(router).await;
+        let mut stream = BufReader::new(TcpStream::connect(addr).await.unwrap());
+
+        stream
+            .write_all(b"POST /keep HTTP/1.1\r\nContent-Length: 4\r\n\r\nkeep")
+            .await
+            .unwrap();
+        assert!(read_response(&mut stream).await.ends_with("kept"));
+
+        // the next request is waiting for its body when the kept one is read
+        stream
+            .write_all(b"POST /echo HTTP/1.1\r\nContent-Length: 8\r\n\r\n")
+            .await
+            .unwrap();
+        assert!(rx.recv().await.unwrap().is_err());
+
+        stream.write_all(b"next-one").await.unwrap();
+        assert!(
+            read_response(&mut stream)
+                .await
+                .ends_with("\r\n\r\nnext-one")
+        );
+    }
+
+    #[test]
+    async fn test_stream_ends_when_client_closes() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let router = HttpRouter::new()
+            .add(
+                HttpMethod::Get,
+                "/events",
+                Arc::new(move |_req| {
+                    let tx = tx.clone();
+                    Box::pin(async move {
+                        let (sender, sse) = Sse::new();
+                        let _ = tx.send(sender);
+                        HttpResponse::from(sse.with_keep_alive(None))
+                    })
+                }),
+            )
+            .await;
+        let addr = serve(router).await;
+        let stream = TcpStream::connect(addr).await.unwrap();
+        stream.set_linger(Some(Duration::ZERO)).unwrap();
+        let mut stream = BufReader::new(stream);
+
+        stream
+            .write_all(b"GET /events HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        assert!(
+            read_response_with(&mut stream, false)
+                .await
+                .contains("200 OK")
+        );
+        let sender = rx.recv().await.unwrap();
+        assert!(!sender.is_closed());
+
+        // nothing is written which could fail, the reset read side tells
+        drop(stream);
+        timeout(Duration::from_secs(5), sender.closed())
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    async fn test_stream_after_client_shuts_down_writing() {
+        let router = HttpRouter::new()
+            .add(
+                HttpMethod::Get,
+                "/slow",
+                Arc::new(|_req| {
+                    Box::pin(async {
+                        let (sender, body) = HttpBody::channel();
+                        tokio::spawn(async move {
+                            tokio::time::sleep(Duration::from_millis(200)).await;
+                            let _ = sender.send("late").await;
+                        });
+                        HttpResponse::new(StatusCode::OK).with_body(body)
+                    })
+                }),
+            )
+            .await;
+        let addr = serve(router).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        // like `nc -N`, the request is followed by EOF
+        stream
+            .write_all(b"GET /slow HTTP/1.1\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        stream.shutdown().await.unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\n4\r\nlate\r\n0\r\n\r\n"));
+    }
+
+    #[test]
+    async fn test_parse_chunk_size() {
+        assert_eq!(parse_chunk_size(b"1A").unwrap(), 26);
+        assert_eq!(parse_chunk_size(b"5 ; name=value").unwrap(), 5);
+        assert!(parse_chunk_size(b"").is_err());
+        assert!(parse_chunk_size(b"+5").is_err());
+        assert!(parse_chunk_size(b"FFFFFFFFFFFFFFFFFF").is_err());
+    }
+
+    #[test]
+    async fn test_pipelined_requests() {
+        let addr = serve(test_router().await).await;
+        let mut stream = BufReader::new(TcpStream::connect(addr).await.unwrap());
+
+        stream
+            .write_all(
+                b"GET /first HTTP/1.1\r\n\r\n\
+                  POST /echo HTTP/1.1\r\n\
+                  Content-Length: 4\r\n\
+                  \r\n\
+                  body\
+                  GET /second HTTP/1.1\r\n\r\n\
+                  POST /ignore HTTP/1.1\r\n\
+                  Transfer-Encoding: chunked\r\n\
+                  \r\n\
+                  3\r\nabc\r\n0\r\n\r\n\
+                  GET /first HTTP/1.1\r\n\
+                  Connection: close\r\n\
+                  \r\n",
+            )
+            .await
+            .unwrap();
+
+        // responses come back in request order
+        for expected in ["/first", "body", "/second", "ignored", "/first"] {
+            let response = read_response(&mut stream).await;
+            assert!(response.starts_with("HTTP/1.1 200 OK"));
+            assert!(
+                response.ends_with(&format!("\r\n\r\n{expected}")),
+                "expected body {expected:?}, got {response:?}"
+            );
+        }
+
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).await.unwrap();
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    async fn test_pipelined_request_split_across_writes() {
+        let addr = serve(test_router().await).await;
+        let mut stream = BufReader::new(TcpStream::connect(addr).await.unwrap());
+
+        // the second request starts in the same write as the first one
+        stream
+            .write_all(b"GET /first HTTP/1.1\r\n\r\nGET /sec")
+            .await
+            .unwrap();
+        let response = read_response(&mut stream).await;
+        assert!(response.ends_with("\r\n\r\n/first"));
+
+        stream.write_all(b"ond HTTP/1.1\r\n\r\n").await.unwrap();
+        let response = read_response(&mut stream).await;
+        assert!(response.ends_with("\r\n\r\n/second"));
+    }
+
+    #[test]
+    async fn test_route_with_query_and_encoded_path() {
+        let addr = serve(test_router().await).await;
+        let mut stream = BufReader::new(TcpStream::connect(addr).await.unwrap());
+
+        stream
+            .write_all(b"GET /first?q=rust HTTP/1.1\r\n\r\nGET /sec%6Fnd HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+
+        let response = read_response(&mut stream).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("\r\n\r\n/first"));
+
+        let response = read_response(&mut stream).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("\r\n\r\n/sec%6Fnd"));
+    }
+
+    #[test]
+    async fn test_lowercase_connection_close() {
+        let addr = serve(test_router().await).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        stream
+            .write_all(b"GET /first HTTP/1.1\r\nconnection: Keep-Alive, close\r\n\r\n")
+            .await
+            .unwrap();
+
+        // the server closes the connection after the response
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.contains("Connection: close"));
+        assert!(response.ends_with("\r\n\r\n/first"));
+    }
+
+    #[test]
+    async fn test_head_uses_get_handler_without_body() {
+        let addr = serve(test_router().await).await;
+        let mut stream = BufReader::new(TcpStream::connect(addr).await.unwrap());
+
+        stream
+            .write_all(b"HEAD /first HTTP/1.1\r\n\r\nGET /second HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+
+        // the length of the GET body is kept, but the body is not sent
+        let response = read_response_with(&mut stream, false).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("Content-Length: 6\r\n"));
+
+        // the next response follows the head directly
+        let response = read_response(&mut stream).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("\r\n\r\n/second"));
+    }
+
+    #[test]
+    async fn test_waiting_for_request_closes_silently() {
+        // no request at all
+        let addr = serve_with(test_router().await, short_timeouts(), |_| {}).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert_eq!(response, "");
+
+        // idle after a keep-alive response
+        let addr = serve_with(test_router().await, short_timeouts(), |_| {}).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /first HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.contains("Connection: keep-alive"));
+        assert!(response.ends_with("\r\n\r\n/first"));
+    }
+
+    #[test]
+    async fn test_slow_request_head_gets_408() {
+        let addr = serve_with(test_router().await, short_timeouts(), |_| {}).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        // the head is never completed
+        stream.write_all(b"GET /first HTTP/1.1\r\n").await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+        assert!(response.contains("Connection: close\r\n"));
+    }
+
+    #[test]
+    async fn test_body_timeout() {
+        async fn collect(mut req: HttpRequest) -> HttpResponse {
+            match req.bytes(1024).await {
+                Ok(data) => HttpResponse::new(StatusCode::OK).with_body(HttpBody::from(data)),
+                Err(e) => e.into(),
+            }
+        }
+
+        let router = HttpRouter::new()
+            .add(
+                HttpMethod::Post,
+                "/collect",
+                Arc::new(|req| Box::pin(collect(req))),
+            )
+            .await;
+        let addr = serve_with(router, short_timeouts(), |_| {}).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        // half of the body, then nothing
+        stream
+            .write_all(b"POST /collect HTTP/1.1\r\nContent-Length: 10\r\n\r\nhalf.")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+        assert!(response.contains("Connection: close\r\n"));
+    }
+
+    #[test]
+    async fn test_max_requests_per_connection() {
+        let addr = serve_with(test_router().await, Timeouts::default(), |connection| {
+            connection.max_requests(Some(2))
+        })
+        .await;
+        let mut stream = BufReader::new(TcpStream::connect(addr).await.unwrap());
+
+        stream
+            .write_all(b"GET /first HTTP/1.1\r\n\r\nGET /second HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        assert!(
+            read_response(&mut stream)
+                .await
+                .contains("Connection: keep-alive")
+        );
+        assert!(
+            read_response(&mut stream)
+                .await
+                .contains("Connection: close")
+        );
+
+        let mut rest = String::new();
+        stream.read_to_string(&mut rest).await.unwrap();
+        assert_eq!(rest, "");
+    }
+
+    #[test]
+    async fn test_write_timeout() {
+        // nobody reads the other end, the write can't make progress
+        let (_client, server) = io::duplex(16);
+        let mut writer = WriteTimeout::new(server, Duration::from_millis(50));
+
+        writer.write_all(b"short").await.unwrap();
+        let err = writer.write_all(&[b'x'; 64]).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    async fn test_not_found_keeps_connection_open() {
+        let router = test_router()
+            .await
+            .error_handler(StatusCode::NOT_FOUND, |status| {
+                HttpResponse::new(status).with_body(HttpBody::from("no such page"))
+            });
+        let addr = serve(router).await;
+        let mut stream = BufReader::new(TcpStream::connect(addr).await.unwrap());
+
+        stream
+            .write_all(b"GET /missing HTTP/1.1\r\n\r\nGET /first HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+
+        let response = read_response(&mut stream).await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(response.contains("Connection: keep-alive\r\n"));
+        assert!(response.ends_with("\r\n\r\nno such page"));
+
+        let response = read_response(&mut stream).await;
+        assert!(response.ends_with("\r\n\r\n/first"));
+    }
+
+    #[test]
+    async fn test_bad_requests_get_error_responses() {
+        async fn send_raw(request: &[u8]) -> String {
+            let addr = serve(test_router().await).await;
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream.write_all(request).await.unwrap();
+
+            // the connection is closed after the error response
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            assert!(response.contains("Connection: close\r\n"));
+            response
+        }
+
+        let response = send_raw(b"GET /first\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+
+        let response = send_raw(b"GET /first HTTP/2.0\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 505 HTTP Version Not Supported\r\n"));
+
+        let response =
+            send_raw(b"POST /echo HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\nab")
+                .await;
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+
+        let mut request = b"GET /first HTTP/1.1\r\nCookie: ".to_vec();
+        request.extend_from_slice(&[b'a'; 9000]);
+        request.extend_from_slice(b"\r\n\r\n");
+        let response = send_raw(&request).await;
+        assert!(response.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+    }
+
+    #[test]
+    async fn test_transfer_encoding_with_content_length() {
+        let addr = serve(test_router().await).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        // the chunks end before `GET`, the length takes its `G` into the body
+        stream
+            .write_all(
+                b"POST /echo HTTP/1.1\r\n\
+                  Content-Length: 6\r\n\
+                  Transfer-Encoding: chunked\r\n\
+                  \r\n\
+                  0\r\n\r\nGET /first HTTP/1.1\r\n\r\n",
+            )
+            .await
+            .unwrap();
+
+        // only an error, the request behind the body is never served
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(response.contains("Connection: close\r\n"));
+        assert_eq!(response.matches("HTTP/1.1").count(), 1);
+    }
+
+    #[test]
+    async fn test_invalid_response_gets_500() {
+        async fn bad_length(_req: HttpRequest) -> HttpResponse {
+            HttpResponse::text("body").insert_header("Content-Length", "four")
+        }
+
+        let addr = serve(HttpRouter::new().get("/bad", bad_length).await).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /bad HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+        assert!(response.contains("Connection: close\r\n"));
+    }
+
+    #[test]
+    async fn test_body_panic_closes_connection() {
+        async fn broken_body(_req: HttpRequest) -> HttpResponse {
+            use crate::{bytes::Bytes, stream::iter};
+
+            // the iterator is lazy, the panic comes while the response is sent
+            let chunks = [Bytes::from("first"), Bytes::new()]
+                .into_iter()
+                .map(|chunk| {
+                    assert!(!chunk.is_empty(), "body bug");
+                    Ok(chunk)
+                });
+            HttpResponse::new(StatusCode::OK).with_body(HttpBody::from_stream(iter(chunks)))
+        }
+
+        let router = HttpRouter::new().get("/broken", broken_body).await;
+        let addr = serve(router).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /broken HTTP/1.1\r\n\r\nGET /broken HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+
+        // the first chunk went out, then the connection is closed without the last chunk
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("5\r\nfirst\r\n"));
+    }
+
+    #[test]
+    async fn test_in_memory_transport() {
+        let (mut client, server) = io::duplex(4096);
+        let mut connection = HttpConnection::new(server, test_router().await, Timeouts::default());
+        let info = ConnectionInfo {
+            peer_addr: Some("192.0.2.1:50000".parse().unwrap()),
+            ..ConnectionInfo::default()
+        };
+        connection.set_info(info.clone());
+        assert_eq!(connection.info(), &info);
+        let task = tokio::spawn(async move { connection.process().await });
+
+        client
+            .write_all(b"POST /echo HTTP/1.1\r\nContent-Length: 4\r\nConnection: close\r\n\r\nping")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nping"));
+        assert!(task.await.unwrap().is_ok());
+    }
+
+    #[test]
+    async fn test_request_connection_info() {
+        async fn client(req: HttpRequest) -> HttpResponse {
+            let info = &req.connection;
+            HttpResponse::text(format!(
+                "{} {} {};",
+                info.request_number,
+                info.client_ip().unwrap(),
+                info.scheme()
+            ))
+        }
+
+        let router = HttpRouter::new().get("/client", client).await;
+        let (mut client, server) = io::duplex(4096);
+        let mut connection = HttpConnection::new(server, router, Timeouts::default());
+        connection.set_info(ConnectionInfo {
+            peer_addr: Some("10.0.0.1:50000".parse().unwrap()),
+            tls: true,
+            ..ConnectionInfo::default()
+        });
+        let proxies = TrustedProxies::new(["10.0.0.0/8".parse().unwrap()]);
+        connection.trusted_proxies(Some(Arc::new(proxies)));
+        let task = tokio::spawn(async move { connection.process().await });
+
+        client
+            .write_all(
+                b"GET /client HTTP/1.1\r\nX-Forwarded-For: 203.0.113.7\r\nX-Forwarded-Proto: http\r\n\r\n\
+                  GET /client HTTP/1.1\r\nConnection: close\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.contains("1 203.0.113.7 http;"));
+        assert!(response.ends_with("2 10.0.0.1 https;"));
+        assert!(task.await.unwrap().is_ok());
+    }
+
+    #[test]
+    async fn test_proxy_protocol() {
+        async fn peer(req: HttpRequest) -> HttpResponse {
+            let info = &req.connection;
+            let version = info.proxy_header.as_ref().unwrap().version;
+            HttpResponse::text(format!("v{version} {}", info.peer_addr.unwrap()))
+        }
+
+        let router = HttpRouter::new().get("/peer", peer).await;
+        let (mut client, server) = io::duplex(4096);
+        let mut connection = HttpConnection::new(server, router.clone(), Timeouts::default());
+        connection.set_info(ConnectionInfo {
+            peer_addr: Some("10.0.0.1:50000".parse().unwrap()),
+            ..ConnectionInfo::default()
+        });
+        connection.proxy_protocol(true);
+        let task = tokio::spawn(async move { connection.process().await });
+
+        // the header may arrive in pieces, the request right behind it
+        client.write_all(b"PROXY TCP4 192.0.2.1 ").await.unwrap();
+        client
+            .write_all(b"198.51.100.1 56324 443\r\nGET /peer HTTP/1.1\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.ends_with("v1 192.0.2.1:56324"));
+        assert!(task.await.unwrap().is_ok());
+
+        // no header, the connection is closed without a response
+        let (mut client, server) = io::duplex(4096);
+        let mut connection = HttpConnection::new(server, router, Timeouts::default());
+        connection.proxy_protocol(true);
+        let task = tokio::spawn(async move { connection.process().await });
+
+        client
+            .write_all(b"GET /peer HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert_eq!(response, "");
+        assert!(matches!(
+            task.await.unwrap(),
+            Err(ServerError::ProtocolError(_))
+        ));
+    }
 }
@@ -1,19 +1,34 @@
 use std::path::Path;
 
 use crate::{
-    body::HttpBody, request::HttpRequest, response::HttpResponse, utils::get_content_type,
+    body::HttpBody, request::HttpRequest, response::HttpResponse, status::StatusCode,
+    utils::get_content_type,
 };
 
 use tokio::{fs::File, io::AsyncReadExt};
 
+/// Path below the root for a decoded request path, `None` if it could leave the root
+fn relative_path(decoded: &str) -> Option<&str> {
+    let path = decoded.strip_prefix('/')?;
+    let safe = path
+        .split('/')
+        .all(|segment| !matches!(segment, "" | "." | "..") && !segment.contains(['\\', '\0']));
+
+    safe.then_some(path)
+}
+
 // TODO: Configurable
 pub async fn file_server_handler(req: HttpRequest) -> HttpResponse {
-    let path = format!("./www{}", req.uri.as_string());
+    let decoded = req.uri.decoded_path();
+    let Some(relative) = relative_path(&decoded) else {
+        return HttpResponse::new(StatusCode::NOT_FOUND).with_body("Not found".into());
+    };
+    let path = format!("./www/{relative}");
 
     println!("File server request for: {path}");
 
     if !Path::new(&path).exists() {
-        return HttpResponse::new(404, "Not Found").with_body("Not found".into());
+        return HttpResponse::new(StatusCode::NOT_FOUND).with_body("Not found".into());
     }
 
     let file = File::open(path).await;
@@ -23,7 +38,7 @@ pub async fn file_server_handler(req: HttpRequest) -> HttpResponse {
                 Ok(meta) => meta,
                 Err(e) => {
                     eprintln!("Failed to get metadata: {e}");
-                    return HttpResponse::new(500, "Internal Server Error")
+                    return HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
                         .with_body("Error reading file metadata".into());
                 }
             };
@@ -31,7 +46,7 @@ pub async fn file_server_handler(req: HttpRequest) -> HttpResponse {
             let file_size = metadata.len() as usize;
 
             if file_size == 0 {
-                return HttpResponse::new(204, "No Content");
+                return HttpResponse::new(StatusCode::NO_CONTENT);
             }
 
             if file_size < 1024 * 1024 {
@@ -40,20 +55,19 @@ pub async fn file_server_handler(req: HttpRequest) -> HttpResponse {
                     Ok(data) => data,
                     Err(e) => {
                         eprintln!("Failed to read file: {e}");
-                        return HttpResponse::new(500, "Internal Server Error")
+                        return HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
                             .with_body("Error reading file".into());
                     }
                 };
 
-                HttpResponse::new(200, "OK")
+                HttpResponse::new(StatusCode::OK)
                     .with_body(HttpBody::from(data))
                     .insert_header("Content-Length", &file_size.to_string())
                     .insert_header("Content-Type", get_content_type(&req.uri.path))
                     .insert_header("Cache-Control", "public, max-age=31536000")
             } else {
-                HttpResponse::new(200, "OK")
-                    .with_streaming_body(file, 8192)
-                    .insert_header("Content-Length", &file_size.to_string())
+                HttpResponse::new(StatusCode::OK)
+                    .with_sized_streaming_body(file, file_size, 8192)
                     .insert_header("Content-Type", get_content_type(&req.uri.path))
                     .insert_header("Accept-Ranges", "bytes")
                     .insert_header("Cache-Control", "public, max-age=31536000")
@@ -61,7 +75,46 @@ pub async fn file_server_handler(req: HttpRequest) -> HttpResponse {
         }
         Err(e) => {
             eprintln!("Failed to open file: {e}");
-            HttpResponse::new(404, "Not Found").with_body("Not found".into())
+            HttpResponse::new(StatusCode::NOT_FOUND).with_body("Not found".into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::test;
+
+    #[test]
+    async fn test_relative_path() {
+        assert_eq!(relative_path("/index.html"), Some("index.html"));
+        assert_eq!(relative_path("/css/site.css"), Some("css/site.css"));
+        assert_eq!(relative_path("/.well-known/x"), Some(".well-known/x"));
+
+        for path in [
+            "/../etc/passwd",
+            "/css/../../etc/passwd",
+            "/./index.html",
+            "//etc/passwd",
+            "/css/",
+            "/..\\etc\\passwd",
+            "/index.html\0.txt",
+            "index.html",
+        ] {
+            assert_eq!(relative_path(path), None, "{path}");
+        }
+    }
+
+    #[test]
+    async fn test_traversal_is_not_found() {
+        for target in [
+            "/%2e%2e/%2e%2e/etc/passwd",
+            "/..%2F..%2Fetc%2Fpasswd",
+            "/../etc/passwd",
+        ] {
+            let request = format!("GET {target} HTTP/1.1\r\n\r\n");
+            let response = file_server_handler(HttpRequest::from(request)).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{target}");
         }
     }
 }
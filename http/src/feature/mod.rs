@@ -1,2 +1,4 @@
 mod file_server;
+mod sse;
 pub use file_server::file_server_handler;
+pub use sse::{DEFAULT_KEEP_ALIVE, Event, SSE_CONTENT_TYPE, Sse, SseSender};
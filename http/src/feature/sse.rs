@@ -0,0 +1,328 @@
+use std::{
+    fmt::Write as _,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io,
+    sync::mpsc,
+    time::{Instant, Sleep, sleep},
+};
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+#[cfg(feature = "serde")]
+use crate::error::ServerError;
+use crate::{
+    body::{DEFAULT_CHANNEL_CAPACITY, HttpBody},
+    bytes::Bytes,
+    request::HttpRequest,
+    response::HttpResponse,
+    status::StatusCode,
+    stream::Stream,
+};
+
+/// Media type of server-sent event streams
+pub const SSE_CONTENT_TYPE: &str = "text/event-stream";
+/// Default interval of keep-alive comments
+pub const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// A server-sent event, see the `text/event-stream` format of the HTML standard
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Event {
+    event: Option<String>,
+    id: Option<String>,
+    data: Option<String>,
+    retry: Option<Duration>,
+    comment: Option<String>,
+}
+
+impl Event {
+    pub fn new() -> Self {
+        Event::default()
+    }
+
+    /// The data, sent as one `data:` line per line
+    pub fn data(mut self, data: impl Into<String>) -> Self {
+        self.data = Some(data.into());
+        self
+    }
+
+    /// The data serialized as JSON
+    #[cfg(feature = "serde")]
+    pub fn json_data<T: Serialize>(self, data: &T) -> Result<Self, ServerError> {
+        let data =
+            serde_json::to_string(data).map_err(|e| ServerError::InternalError(e.to_string()))?;
+        Ok(self.data(data))
+    }
+
+    /// The event type, `message` if not set
+    pub fn event(mut self, event: impl Into<String>) -> Self {
+        self.event = Some(event.into());
+        self
+    }
+
+    /// The id the client sends back in `Last-Event-ID` when it reconnects
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// How long the client waits before reconnecting
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// A comment, ignored by the client
+    pub fn comment(mut self, comment: impl Into<String>) -> Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    /// Serialize the event, line breaks in single line fields are dropped
+    pub fn encode(&self) -> String {
+        let mut buf = String::new();
+
+        if let Some(comment) = &self.comment {
+            for line in split_lines(comment) {
+                let _ = writeln!(buf, ": {line}");
+            }
+        }
+        if let Some(event) = &self.event {
+            let _ = writeln!(buf, "event: {}", single_line(event).collect::<String>());
+        }
+        if let Some(id) = &self.id {
+            // a NUL makes the client ignore the id
+            let id: String = single_line(id).filter(|&c| c != '\0').collect();
+            let _ = writeln!(buf, "id: {id}");
+        }
+        if let Some(retry) = self.retry {
+            let _ = writeln!(buf, "retry: {}", retry.as_millis());
+        }
+        if let Some(data) = &self.data {
+            for line in split_lines(data) {
+                let _ = writeln!(buf, "data: {line}");
+            }
+        }
+
+        buf.push('\n');
+        buf
+    }
+}
+
+/// Lines separated by CRLF, LF or CR
+fn split_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.split("\r\n").flat_map(|line| line.split(['\r', '\n']))
+}
+
+fn single_line(text: &str) -> impl Iterator<Item = char> + '_ {
+    text.chars().filter(|&c| c != '\r' && c != '\n')
+}
+
+/// A `text/event-stream` response fed through an `SseSender`
+///
+/// Every event is flushed as soon as it is written. A comment is sent when no
+/// event was sent for the keep-alive interval, which also notices a client
+/// that went away: the body is then dropped and the sender fails.
+pub struct Sse {
+    receiver: mpsc::Receiver<Event>,
+    keep_alive: Option<Duration>,
+}
+
+impl Sse {
+    /// Create the response and the sender to feed it
+    pub fn new() -> (SseSender, Self) {
+        let (sender, receiver) = mpsc::channel(DEFAULT_CHANNEL_CAPACITY);
+        let sse = Sse {
+            receiver,
+            keep_alive: Some(DEFAULT_KEEP_ALIVE),
+        };
+
+        (SseSender { sender }, sse)
+    }
+
+    /// Interval of keep-alive comments, `None` to disable them
+    pub fn with_keep_alive(mut self, interval: Option<Duration>) -> Self {
+        self.keep_alive = interval;
+        self
+    }
+}
+
+impl From<Sse> for HttpResponse {
+    fn from(value: Sse) -> Self {
+        let body = SseBody {
+            receiver: value.receiver,
+            keep_alive: value
+                .keep_alive
+                .map(|interval| (interval, Box::pin(sleep(interval)))),
+            // the head goes out right away, so the client knows it is connected
+            flush: true,
+        };
+
+        HttpResponse::new(StatusCode::OK)
+            .with_body(HttpBody::from_stream(body))
+            .insert_header("Content-Type", SSE_CONTENT_TYPE)
+            .insert_header("Cache-Control", "no-cache")
+    }
+}
+
+/// Sending half of an `Sse` response
+#[derive(Debug, Clone)]
+pub struct SseSender {
+    sender: mpsc::Sender<Event>,
+}
+
+impl SseSender {
+    /// Send an event, wait if the client is slow
+    ///
+    /// Fails with `BrokenPipe` once the response is dropped, such as when the
+    /// client disconnected. The producer should stop then.
+    pub async fn send(&self, event: Event) -> io::Result<()> {
+        self.sender
+            .send(event)
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the event stream was dropped"))
+    }
+
+    /// Wait until the response is dropped
+    pub async fn closed(&self) {
+        self.sender.closed().await
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+}
+
+/// Encoded events, each one followed by an empty chunk to flush it
+struct SseBody {
+    receiver: mpsc::Receiver<Event>,
+    keep_alive: Option<(Duration, Pin<Box<Sleep>>)>,
+    /// The last chunk still has to be flushed
+    flush: bool,
+}
+
+impl SseBody {
+    fn reset_keep_alive(&mut self) {
+        if let Some((interval, timer)) = self.keep_alive.as_mut() {
+            timer.as_mut().reset(Instant::now() + *interval);
+        }
+    }
+}
+
+impl Stream for SseBody {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.flush {
+            self.flush = false;
+            return Poll::Ready(Some(Ok(Bytes::new())));
+        }
+
+        if let Poll::Ready(event) = self.receiver.poll_recv(cx) {
+            return match event {
+                Some(event) => {
+                    self.flush = true;
+                    self.reset_keep_alive();
+                    Poll::Ready(Some(Ok(Bytes::from(event.encode()))))
+                }
+                None => Poll::Ready(None),
+            };
+        }
+
+        if let Some((_, timer)) = self.keep_alive.as_mut()
+            && timer.as_mut().poll(cx).is_ready()
+        {
+            self.flush = true;
+            self.reset_keep_alive();
+            return Poll::Ready(Some(Ok(Bytes::from_static(b":\n\n"))));
+        }
+
+        Poll::Pending
+    }
+}
+
+impl HttpRequest {
+    /// The id of the last event a reconnecting client received
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.headers.get("Last-Event-ID").map(|id| id.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::method::HttpMethod;
+    use tokio::io::AsyncReadExt;
+    use tokio::test;
+
+    #[test]
+    async fn test_event_encoding() {
+        let event = Event::new()
+            .event("update")
+            .id("42")
+            .retry(Duration::from_secs(3))
+            .data("first\nsecond\r\nthird\rfourth");
+        assert_eq!(
+            event.encode(),
+            "event: update\nid: 42\nretry: 3000\n\
+             data: first\ndata: second\ndata: third\ndata: fourth\n\n"
+        );
+
+        // line breaks can't start new fields
+        let event = Event::new()
+            .comment("two\nlines")
+            .event("in\r\njected")
+            .id("a\nb\0")
+            .data("");
+        assert_eq!(
+            event.encode(),
+            ": two\n: lines\nevent: injected\nid: ab\ndata: \n\n"
+        );
+    }
+
+    #[test]
+    async fn test_last_event_id() {
+        let req = HttpRequest::from("GET /events HTTP/1.1\r\nLast-Event-ID: 41\r\n\r\n");
+        assert_eq!(req.last_event_id(), Some("41"));
+
+        let req = HttpRequest::from("GET /events HTTP/1.1\r\n\r\n");
+        assert_eq!(req.last_event_id(), None);
+    }
+
+    #[test(start_paused = true)]
+    async fn test_stream_keep_alive_and_disconnect() {
+        async fn read_some(client: &mut io::DuplexStream) -> String {
+            let mut buf = [0u8; 256];
+            let n = client.read(&mut buf).await.unwrap();
+            String::from_utf8_lossy(&buf[..n]).into_owned()
+        }
+
+        let (sender, sse) = Sse::new();
+        let mut response = HttpResponse::from(sse.with_keep_alive(Some(Duration::from_secs(10))));
+        let (mut client, mut server) = io::duplex(1024);
+        let task = tokio::spawn(async move { response.send(&mut server, HttpMethod::Get).await });
+
+        // the head is flushed before the first event
+        let head = read_some(&mut client).await;
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains("Content-Type: text/event-stream\r\n"));
+        assert!(head.contains("Transfer-Encoding: chunked\r\n"));
+
+        sender.send(Event::new().data("hello")).await.unwrap();
+        assert_eq!(read_some(&mut client).await, "D\r\ndata: hello\n\n\r\n");
+
+        // nothing to send, a comment keeps the connection alive
+        assert_eq!(read_some(&mut client).await, "3\r\n:\n\n\r\n");
+
+        // the client went away, the producer is told to stop
+        drop(client);
+        sender.closed().await;
+        assert!(sender.send(Event::new().data("lost")).await.is_err());
+        assert!(task.await.unwrap().is_err());
+    }
+}
@@ -0,0 +1,378 @@
+use std::fmt;
+
+use tokio::io;
+
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
+
+use crate::{
+    body::{BodyError, HttpBody},
+    request::HttpRequest,
+    response::HttpResponse,
+    status::StatusCode,
+    uri::{EncodeSet, percent_decode, percent_encode},
+};
+
+/// Media type of url-encoded form bodies
+pub const FORM_CONTENT_TYPE: &str = "application/x-www-form-urlencoded";
+/// Default size limit of a form body
+pub const DEFAULT_FORM_LIMIT: usize = 1024 * 1024;
+
+#[derive(Debug)]
+pub enum FormError {
+    /// The request is not `application/x-www-form-urlencoded`
+    UnsupportedContentType(Option<String>),
+    /// The pairs can't be deserialized into the target type
+    Invalid(String),
+    /// Reading the body failed, such as when it is larger than the limit
+    Body(BodyError),
+}
+
+impl FormError {
+    /// Status of the response to send for this error
+    pub fn status(&self) -> StatusCode {
+        match self {
+            FormError::UnsupportedContentType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            FormError::Invalid(_) => StatusCode::BAD_REQUEST,
+            FormError::Body(e) => e.status(),
+        }
+    }
+}
+
+/// A message which can be sent to the client
+impl fmt::Display for FormError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormError::UnsupportedContentType(Some(content_type)) => {
+                write!(f, "expected {FORM_CONTENT_TYPE}, got {content_type}")
+            }
+            FormError::UnsupportedContentType(None) => {
+                write!(f, "expected {FORM_CONTENT_TYPE}, got no content type")
+            }
+            FormError::Invalid(message) => write!(f, "invalid form: {message}"),
+            FormError::Body(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl From<FormError> for HttpResponse {
+    fn from(value: FormError) -> Self {
+        let mut response = HttpResponse::text(value.to_string());
+        response.set_status(value.status());
+        response
+    }
+}
+
+impl From<BodyError> for FormError {
+    fn from(value: BodyError) -> Self {
+        FormError::Body(value)
+    }
+}
+
+impl From<io::Error> for FormError {
+    fn from(value: io::Error) -> Self {
+        FormError::Body(BodyError::IOError(value))
+    }
+}
+
+/// Parse `application/x-www-form-urlencoded` data, such as a query string
+///
+/// - pairs keep their order, repeated keys are all kept
+/// - `+` is decoded as a space and `%XX` escapes are decoded
+/// - a key without `=` has an empty value, empty pairs are skipped
+/// - invalid UTF-8 is replaced with U+FFFD
+pub fn parse_urlencoded(input: &[u8]) -> Vec<(String, String)> {
+    input
+        .split(|&b| b == b'&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = match pair.iter().position(|&b| b == b'=') {
+                Some(pos) => (&pair[..pos], &pair[pos + 1..]),
+                None => (pair, &b""[..]),
+            };
+            (decode_component(key), decode_component(value))
+        })
+        .collect()
+}
+
+fn decode_component(input: &[u8]) -> String {
+    let input: Vec<u8> = input
+        .iter()
+        .map(|&b| if b == b'+' { b' ' } else { b })
+        .collect();
+
+    String::from_utf8_lossy(&percent_decode(&input)).into_owned()
+}
+
+/// Encode pairs as `application/x-www-form-urlencoded`, spaces become `+`
+pub fn encode_urlencoded<K, V>(pairs: impl IntoIterator<Item = (K, V)>) -> String
+where
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    pairs
+        .into_iter()
+        .map(|(key, value)| {
+            format!(
+                "{}={}",
+                encode_component(key.as_ref()),
+                encode_component(value.as_ref())
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn encode_component(input: &str) -> String {
+    input
+        .split(' ')
+        .map(|part| percent_encode(part.as_bytes(), EncodeSet::Component))
+        .collect::<Vec<_>>()
+        .join("+")
+}
+
+/// Check that the request has a url-encoded form body
+fn check_content_type(req: &HttpRequest) -> Result<(), FormError> {
+    let content_type = req.headers.get("Content-Type");
+    let is_form = content_type.is_some_and(|value| {
+        let media_type = value.split(';').next().unwrap_or("").trim();
+        media_type.eq_ignore_ascii_case(FORM_CONTENT_TYPE)
+    });
+
+    match is_form {
+        true => Ok(()),
+        false => Err(FormError::UnsupportedContentType(content_type.cloned())),
+    }
+}
+
+/// Read the whole body, fail if it is larger than `limit`
+async fn read_limited(body: &mut HttpBody, limit: usize) -> Result<Vec<u8>, FormError> {
+    Ok(body.collect(limit).await?.into_vec())
+}
+
+impl HttpRequest {
+    /// Decoded pairs of the query string, see `parse_urlencoded`
+    pub fn query_pairs(&self) -> Vec<(String, String)> {
+        self.uri
+            .query
+            .as_deref()
+            .map(|query| parse_urlencoded(query.as_bytes()))
+            .unwrap_or_default()
+    }
+
+    /// Read a url-encoded form body of at most `limit` bytes and decode it
+    pub async fn form_pairs(&mut self, limit: usize) -> Result<Vec<(String, String)>, FormError> {
+        check_content_type(self)?;
+
+        let data = match self.body.as_mut() {
+            Some(body) => read_limited(body, limit).await?,
+            None => Vec::new(),
+        };
+
+        Ok(parse_urlencoded(&data))
+    }
+}
+
+/// Query string deserialized into `T`
+///
+/// Repeated keys are an error for plain fields, use a `Vec` of pairs to keep them.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query<T>(pub T);
+
+#[cfg(feature = "serde")]
+impl<T: DeserializeOwned> Query<T> {
+    pub fn from_request(req: &HttpRequest) -> Result<Self, FormError> {
+        let query = req.uri.query.as_deref().unwrap_or("");
+
+        serde_urlencoded::from_str(query)
+            .map(Query)
+            .map_err(|e| FormError::Invalid(e.to_string()))
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+/// Url-encoded form body deserialized into `T`
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Form<T>(pub T);
+
+#[cfg(feature = "serde")]
+impl<T: DeserializeOwned> Form<T> {
+    /// Read a form body of at most `limit` bytes
+    pub async fn from_request(req: &mut HttpRequest, limit: usize) -> Result<Self, FormError> {
+        check_content_type(req)?;
+
+        let data = match req.body.as_mut() {
+            Some(body) => read_limited(body, limit).await?,
+            None => Vec::new(),
+        };
+
+        serde_urlencoded::from_bytes(&data)
+            .map(Form)
+            .map_err(|e| FormError::Invalid(e.to_string()))
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::test;
+
+    fn pairs(input: &[(&str, &str)]) -> Vec<(String, String)> {
+        input
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    async fn test_parse_urlencoded() {
+        assert_eq!(
+            parse_urlencoded(b"a=1&b=hello+world&a=2"),
+            pairs(&[("a", "1"), ("b", "hello world"), ("a", "2")])
+        );
+        assert_eq!(
+            parse_urlencoded(b"flag&&empty=&=value&x=1=2"),
+            pairs(&[("flag", ""), ("empty", ""), ("", "value"), ("x", "1=2")])
+        );
+        // `%2B` is a literal plus, `+` is a space
+        assert_eq!(
+            parse_urlencoded(b"q=1%2B1+%3D+2&%E4%BD%A0=%E5%A5%BD"),
+            pairs(&[("q", "1+1 = 2"), ("你", "好")])
+        );
+        // invalid UTF-8 is replaced
+        assert_eq!(
+            parse_urlencoded(b"bad=%FF%FE&raw=\xff"),
+            pairs(&[("bad", "\u{FFFD}\u{FFFD}"), ("raw", "\u{FFFD}")])
+        );
+        assert!(parse_urlencoded(b"").is_empty());
+    }
+
+    #[test]
+    async fn test_encode_urlencoded() {
+        let encoded = encode_urlencoded([("name", "a b&c"), ("plus", "1+1"), ("你", "")]);
+        assert_eq!(encoded, "name=a+b%26c&plus=1%2B1&%E4%BD%A0=");
+        assert_eq!(
+            parse_urlencoded(encoded.as_bytes()),
+            pairs(&[("name", "a b&c"), ("plus", "1+1"), ("你", "")])
+        );
+    }
+
+    #[test]
+    async fn test_query_pairs() {
+        let req = HttpRequest::from("GET /search?q=rust+http&tag=a&tag=b HTTP/1.1\r\n\r\n");
+        assert_eq!(
+            req.query_pairs(),
+            pairs(&[("q", "rust http"), ("tag", "a"), ("tag", "b")])
+        );
+
+        let req = HttpRequest::from("GET /search HTTP/1.1\r\n\r\n");
+        assert!(req.query_pairs().is_empty());
+    }
+
+    #[test]
+    async fn test_form_pairs() {
+        let mut req = HttpRequest::from(
+            "POST /form HTTP/1.1\r\n\
+             Content-Type: application/x-www-form-urlencoded; charset=utf-8\r\n\
+             \r\n\
+             name=Ferris&lang=rust",
+        );
+        assert_eq!(
+            req.form_pairs(DEFAULT_FORM_LIMIT).await.unwrap(),
+            pairs(&[("name", "Ferris"), ("lang", "rust")])
+        );
+    }
+
+    #[test]
+    async fn test_form_limit_and_content_type() {
+        let mut req = HttpRequest::from(
+            "POST /form HTTP/1.1\r\n\
+             Content-Type: application/x-www-form-urlencoded\r\n\
+             \r\n\
+             name=Ferris&lang=rust",
+        );
+        assert!(matches!(
+            req.form_pairs(8).await,
+            Err(FormError::Body(BodyError::LimitExceeded(8)))
+        ));
+
+        let mut req = HttpRequest::from(
+            "POST /form HTTP/1.1\r\n\
+             Content-Type: application/json\r\n\
+             \r\n\
+             {}",
+        );
+        let error = req.form_pairs(DEFAULT_FORM_LIMIT).await.unwrap_err();
+        assert!(matches!(error, FormError::UnsupportedContentType(Some(_))));
+        assert_eq!(error.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[test]
+    async fn test_form_error_responses() {
+        let response = HttpResponse::from(FormError::Body(BodyError::LimitExceeded(8)));
+        assert_eq!(response.status(), StatusCode::CONTENT_TOO_LARGE);
+
+        let response = HttpResponse::from(FormError::Invalid("missing field `q`".to_string()));
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let timeout = io::Error::new(io::ErrorKind::TimedOut, "request body timeout");
+        assert_eq!(
+            FormError::from(timeout).status(),
+            StatusCode::REQUEST_TIMEOUT
+        );
+        assert_eq!(
+            FormError::UnsupportedContentType(None).to_string(),
+            "expected application/x-www-form-urlencoded, got no content type"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    async fn test_typed_query_and_form() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Search {
+            q: String,
+            page: Option<u32>,
+        }
+
+        let req = HttpRequest::from("GET /search?q=rust+http&page=2 HTTP/1.1\r\n\r\n");
+        let Query(search) = Query::<Search>::from_request(&req).unwrap();
+        assert_eq!(
+            search,
+            Search {
+                q: "rust http".to_string(),
+                page: Some(2)
+            }
+        );
+
+        // repeated keys are an error for plain fields
+        let req = HttpRequest::from("GET /search?q=a&q=b HTTP/1.1\r\n\r\n");
+        assert!(matches!(
+            Query::<Search>::from_request(&req),
+            Err(FormError::Invalid(_))
+        ));
+        // but can be kept as pairs
+        let Query(all) = Query::<Vec<(String, String)>>::from_request(&req).unwrap();
+        assert_eq!(all, pairs(&[("q", "a"), ("q", "b")]));
+
+        let mut req = HttpRequest::from(
+            "POST /search HTTP/1.1\r\n\
+             Content-Type: application/x-www-form-urlencoded\r\n\
+             \r\n\
+             q=%FF&page=x",
+        );
+        assert!(matches!(
+            Form::<Search>::from_request(&mut req, DEFAULT_FORM_LIMIT).await,
+            Err(FormError::Invalid(_))
+        ));
+    }
+}
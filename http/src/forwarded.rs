@@ -0,0 +1,316 @@
+use std::{fmt, net::IpAddr, str::FromStr};
+
+use crate::{connect::ConnectionInfo, error::ServerError, headers::HttpHeaders};
+
+/// A range of IP addresses, such as `10.0.0.0/8` or `fd00::/8`
+///
+/// IPv4 addresses mapped into IPv6 match the IPv4 ranges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// The range of addresses sharing the first `prefix` bits with `addr`
+    pub fn new(addr: IpAddr, prefix: u8) -> Result<Self, ServerError> {
+        let addr = addr.to_canonical();
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix > max {
+            return Err(ServerError::ConfigError(format!(
+                "prefix /{prefix} is too long for {addr}"
+            )));
+        }
+
+        Ok(Cidr { addr, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                prefix_eq(net.to_bits().into(), ip.to_bits().into(), 32, self.prefix)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_eq(net.to_bits(), ip.to_bits(), 128, self.prefix)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Whether the first `prefix` of `bits` bits are equal
+fn prefix_eq(a: u128, b: u128, bits: u8, prefix: u8) -> bool {
+    let shift = bits - prefix;
+    (a ^ b).checked_shr(shift.into()).unwrap_or(0) == 0
+}
+
+/// Parsed from `addr/prefix`, an address alone is a single host
+impl FromStr for Cidr {
+    type Err = ServerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ServerError::ParseError(format!("invalid CIDR: {s}"));
+        match s.split_once('/') {
+            Some((addr, prefix)) => Cidr::new(
+                addr.parse().map_err(|_| invalid())?,
+                prefix.parse().map_err(|_| invalid())?,
+            ),
+            None => {
+                let addr: IpAddr = s.parse().map_err(|_| invalid())?;
+                Cidr::new(addr, if addr.is_ipv4() { 32 } else { 128 })
+            }
+        }
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// The client as reported by trusted proxies
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForwardedClient {
+    /// `None` if the proxy hides the address, such as `for=unknown`
+    pub ip: Option<IpAddr>,
+    /// `http` or `https`, if the proxy reported it
+    pub proto: Option<String>,
+}
+
+/// Proxies whose `Forwarded` and `X-Forwarded-*` headers are believed
+///
+/// The headers are read from the right, the hop added by the closest proxy,
+/// and skip every hop which is a trusted proxy too. The first hop which is
+/// not trusted is the client. Headers of untrusted peers are ignored, as any
+/// client can send them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies {
+    cidrs: Vec<Cidr>,
+    unix: bool,
+}
+
+impl TrustedProxies {
+    pub fn new(cidrs: impl IntoIterator<Item = Cidr>) -> Self {
+        TrustedProxies {
+            cidrs: cidrs.into_iter().collect(),
+            unix: false,
+        }
+    }
+
+    /// Also trust every peer of a Unix socket, such as a local reverse proxy
+    pub fn trust_unix_peers(mut self) -> Self {
+        self.unix = true;
+        self
+    }
+
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.cidrs.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    /// The client behind the proxies, `None` if the peer isn't trusted or sent no forwarding headers
+    ///
+    /// `Forwarded` is preferred over `X-Forwarded-For` and `X-Forwarded-Proto`.
+    pub fn resolve(&self, info: &ConnectionInfo, headers: &HttpHeaders) -> Option<ForwardedClient> {
+        let trusted = match info.peer_addr {
+            Some(addr) => self.is_trusted(addr.ip()),
+            None => self.unix && info.peer_cred.is_some(),
+        };
+        if !trusted {
+            return None;
+        }
+
+        let hops = match headers.contains_key("Forwarded") {
+            true => forwarded_hops(headers),
+            false => x_forwarded_hops(headers),
+        };
+        let client = hops
+            .iter()
+            .rev()
+            .find(|hop| !hop.ip.is_some_and(|ip| self.is_trusted(ip)))
+            .or(hops.first())?;
+
+        Some(client.clone())
+    }
+}
+
+/// Hops of `Forwarded: for=192.0.2.60;proto=http, for="[2001:db8::1]"`
+fn forwarded_hops(headers: &HttpHeaders) -> Vec<ForwardedClient> {
+    headers
+        .get_list("Forwarded")
+        .into_iter()
+        .map(|element| {
+            let mut hop = ForwardedClient {
+                ip: None,
+                proto: None,
+            };
+            for pair in element.split(';') {
+                let Some((key, value)) = pair.split_once('=') else {
+                    continue;
+                };
+                let value = value.trim().trim_matches('"');
+                match key.trim().to_ascii_lowercase().as_str() {
+                    "for" => hop.ip = parse_node(value),
+                    "proto" => hop.proto = parse_proto(value),
+                    _ => {}
+                }
+            }
+            hop
+        })
+        .collect()
+}
+
+/// Hops of `X-Forwarded-For`, `X-Forwarded-Proto` is matched up from the right
+fn x_forwarded_hops(headers: &HttpHeaders) -> Vec<ForwardedClient> {
+    let ips = headers.get_list("X-Forwarded-For");
+    let protos = headers.get_list("X-Forwarded-Proto");
+
+    ips.iter()
+        .enumerate()
+        .map(|(i, ip)| {
+            let proto = match protos.as_slice() {
+                // usually only the edge proxy sets it
+                [proto] => Some(*proto),
+                _ => (i + protos.len())
+                    .checked_sub(ips.len())
+                    .and_then(|j| protos.get(j).copied()),
+            };
+            ForwardedClient {
+                ip: parse_node(ip),
+                proto: proto.and_then(parse_proto),
+            }
+        })
+        .collect()
+}
+
+/// An address with an optional port, `unknown` and obfuscated names are `None`
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim();
+    if let Some(rest) = node.strip_prefix('[') {
+        let (ip, _) = rest.split_once(']')?;
+        return ip.parse().ok();
+    }
+
+    node.parse().ok().or_else(|| {
+        let (ip, _port) = node.rsplit_once(':')?;
+        ip.parse().ok()
+    })
+}
+
+fn parse_proto(proto: &str) -> Option<String> {
+    let proto = proto.trim().to_ascii_lowercase();
+    matches!(proto.as_str(), "http" | "https").then_some(proto)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connect::PeerCred;
+
+    fn info(peer: &str) -> ConnectionInfo {
+        ConnectionInfo {
+            peer_addr: Some(peer.parse().unwrap()),
+            ..ConnectionInfo::default()
+        }
+    }
+
+    fn headers(lines: &[(&str, &str)]) -> HttpHeaders {
+        let mut headers = HttpHeaders::new();
+        for (name, value) in lines {
+            headers.append(name, value);
+        }
+        headers
+    }
+
+    fn proxies() -> TrustedProxies {
+        TrustedProxies::new(["10.0.0.0/8".parse().unwrap(), "fd00::/8".parse().unwrap()])
+    }
+
+    #[test]
+    fn test_cidr() {
+        let cidr: Cidr = "192.168.0.0/16".parse().unwrap();
+        assert!(cidr.contains("192.168.1.2".parse().unwrap()));
+        assert!(cidr.contains("::ffff:192.168.1.2".parse().unwrap()));
+        assert!(!cidr.contains("192.169.0.1".parse().unwrap()));
+        assert!(!cidr.contains("fd00::1".parse().unwrap()));
+        assert_eq!(cidr.to_string(), "192.168.0.0/16");
+
+        let host: Cidr = "2001:db8::1".parse().unwrap();
+        assert!(host.contains("2001:db8::1".parse().unwrap()));
+        assert!(!host.contains("2001:db8::2".parse().unwrap()));
+
+        let all: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(all.contains("203.0.113.9".parse().unwrap()));
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("10.0.0/8".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn test_x_forwarded_for() {
+        let headers = headers(&[
+            ("X-Forwarded-For", "198.51.100.1, 203.0.113.7"),
+            ("X-Forwarded-For", "10.1.1.1"),
+            ("X-Forwarded-Proto", "https"),
+        ]);
+
+        // the closest untrusted hop, not the spoofable leftmost one
+        let client = proxies().resolve(&info("10.0.0.2:4000"), &headers).unwrap();
+        assert_eq!(client.ip, Some("203.0.113.7".parse().unwrap()));
+        assert_eq!(client.proto.as_deref(), Some("https"));
+
+        // an untrusted peer can't claim to be someone else
+        assert_eq!(proxies().resolve(&info("203.0.113.7:4000"), &headers), None);
+    }
+
+    #[test]
+    fn test_forwarded() {
+        let forwarded = headers(&[
+            (
+                "Forwarded",
+                r#"for=192.0.2.60;proto=http, for="[fd00::17]:4711";proto=https"#,
+            ),
+            ("X-Forwarded-For", "198.51.100.1"),
+        ]);
+
+        let client = proxies()
+            .resolve(&info("[fd00::1]:443"), &forwarded)
+            .unwrap();
+        assert_eq!(client.ip, Some("192.0.2.60".parse().unwrap()));
+        assert_eq!(client.proto.as_deref(), Some("http"));
+
+        // a hidden client is still not the proxy
+        let hidden = headers(&[("Forwarded", "for=_hidden, for=10.2.2.2")]);
+        let client = proxies().resolve(&info("10.0.0.2:4000"), &hidden).unwrap();
+        assert_eq!(client.ip, None);
+
+        // only trusted hops, the leftmost one is the client
+        let proxied = headers(&[("Forwarded", "for=10.3.3.3;proto=ftp")]);
+        let client = proxies().resolve(&info("10.0.0.2:4000"), &proxied).unwrap();
+        assert_eq!(client.ip, Some("10.3.3.3".parse().unwrap()));
+        assert_eq!(client.proto, None);
+    }
+
+    #[test]
+    fn test_unix_peers() {
+        let headers = headers(&[("X-Forwarded-For", "203.0.113.7")]);
+        let info = ConnectionInfo {
+            peer_cred: Some(PeerCred {
+                uid: 0,
+                gid: 0,
+                pid: None,
+            }),
+            ..ConnectionInfo::default()
+        };
+
+        assert_eq!(proxies().resolve(&info, &headers), None);
+        let client = proxies()
+            .trust_unix_peers()
+            .resolve(&info, &headers)
+            .unwrap();
+        assert_eq!(client.ip, Some("203.0.113.7".parse().unwrap()));
+    }
+}
@@ -4,6 +4,7 @@ use std::sync::Arc;
 
 use crate::request::HttpRequest;
 use crate::response::HttpResponse;
+use crate::status::StatusCode;
 
 /// Handler function type
 ///
@@ -20,6 +21,7 @@ use crate::response::HttpResponse;
 /// use std::sync::Arc;
 /// use http::request::HttpRequest;
 /// use http::response::HttpResponse;
+/// use http::status::StatusCode;
 ///
 /// type HandlerFn = Arc<
 ///     dyn Fn(HttpRequest) -> Pin<Box<dyn Future<Output = HttpResponse> + Send + 'static>>
@@ -30,7 +32,7 @@ use crate::response::HttpResponse;
 ///
 /// async fn example_handler(req: HttpRequest) -> HttpResponse {
 ///     // Process the request and return a response
-///     HttpResponse::new(200, "OK")
+///     HttpResponse::new(StatusCode::OK)
 ///         .with_body("Hello, world!".into())
 /// }
 ///
@@ -44,3 +46,9 @@ pub type HandlerFn = Arc<
         + Sync
         + 'static,
 >;
+
+/// Error handler function type
+///
+/// Builds the response for an error the connection answers by itself, such
+/// as a malformed request or a path without a route.
+pub type ErrorHandlerFn = Arc<dyn Fn(StatusCode) -> HttpResponse + Send + Sync + 'static>;
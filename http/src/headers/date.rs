@@ -0,0 +1,337 @@
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const LONG_WEEKDAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// HTTP-date with second precision, see RFC 9110 section 5.6.7
+///
+/// Formatted as IMF-fixdate (`Sun, 06 Nov 1994 08:49:37 GMT`). Parsing also
+/// accepts the obsolete RFC 850 and asctime formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HttpDate {
+    /// Seconds since the unix epoch
+    secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidDate(pub String);
+
+impl HttpDate {
+    pub fn now() -> Self {
+        HttpDate::from(SystemTime::now())
+    }
+
+    pub fn from_unix_secs(secs: u64) -> Self {
+        HttpDate { secs }
+    }
+
+    pub fn unix_secs(&self) -> u64 {
+        self.secs
+    }
+
+    fn from_parts(date: Parts) -> Result<Self, InvalidDate> {
+        let invalid = || InvalidDate(format!("{date:?}"));
+
+        let days_in_month = match date.month {
+            2 if is_leap_year(date.year) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        };
+        if date.year < 1970
+            || !(1..=12).contains(&date.month)
+            || !(1..=days_in_month).contains(&date.day)
+            || date.hour > 23
+            || date.minute > 59
+            || date.second > 60
+        {
+            return Err(invalid());
+        }
+
+        let days = days_from_civil(date.year, date.month, date.day);
+        if let Some(weekday) = date.weekday
+            && weekday != weekday_of(days)
+        {
+            return Err(invalid());
+        }
+
+        // a leap second is folded into the previous one
+        let second = date.second.min(59);
+        let secs = days * 86400 + date.hour * 3600 + date.minute * 60 + second;
+
+        Ok(HttpDate { secs })
+    }
+}
+
+impl From<SystemTime> for HttpDate {
+    /// Times before the unix epoch are clamped to it, sub-second precision is dropped
+    fn from(value: SystemTime) -> Self {
+        let secs = value
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+
+        HttpDate { secs }
+    }
+}
+
+impl From<HttpDate> for SystemTime {
+    fn from(value: HttpDate) -> Self {
+        UNIX_EPOCH + Duration::from_secs(value.secs)
+    }
+}
+
+impl fmt::Display for HttpDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let days = self.secs / 86400;
+        let time = self.secs % 86400;
+        let (year, month, day) = civil_from_days(days);
+
+        write!(
+            f,
+            "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+            WEEKDAYS[weekday_of(days)],
+            day,
+            MONTHS[month as usize - 1],
+            year,
+            time / 3600,
+            time % 3600 / 60,
+            time % 60
+        )
+    }
+}
+
+impl FromStr for HttpDate {
+    type Err = InvalidDate;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = parse_imf_fixdate(s)
+            .or_else(|| parse_rfc850(s))
+            .or_else(|| parse_asctime(s))
+            .ok_or_else(|| InvalidDate(s.to_string()))?;
+
+        HttpDate::from_parts(parts).map_err(|_| InvalidDate(s.to_string()))
+    }
+}
+
+#[derive(Debug)]
+struct Parts {
+    year: u64,
+    month: u64,
+    day: u64,
+    hour: u64,
+    minute: u64,
+    second: u64,
+    /// 0 is Sunday
+    weekday: Option<usize>,
+}
+
+/// Parse exactly `len` ASCII digits
+fn digits(s: &str, len: usize) -> Option<u64> {
+    match s.len() == len && s.bytes().all(|b| b.is_ascii_digit()) {
+        true => s.parse().ok(),
+        false => None,
+    }
+}
+
+fn month_of(s: &str) -> Option<u64> {
+    MONTHS
+        .iter()
+        .position(|month| *month == s)
+        .map(|i| i as u64 + 1)
+}
+
+/// `HH:MM:SS`
+fn parse_time(s: &str) -> Option<(u64, u64, u64)> {
+    let mut parts = s.split(':');
+    let time = (
+        digits(parts.next()?, 2)?,
+        digits(parts.next()?, 2)?,
+        digits(parts.next()?, 2)?,
+    );
+
+    match parts.next() {
+        Some(_) => None,
+        None => Some(time),
+    }
+}
+
+/// `Sun, 06 Nov 1994 08:49:37 GMT`
+fn parse_imf_fixdate(s: &str) -> Option<Parts> {
+    let (weekday, rest) = s.split_once(", ")?;
+    let fields: Vec<&str> = rest.split(' ').collect();
+    if fields.len() != 5 || fields[4] != "GMT" {
+        return None;
+    }
+
+    let (hour, minute, second) = parse_time(fields[3])?;
+    Some(Parts {
+        year: digits(fields[2], 4)?,
+        month: month_of(fields[1])?,
+        day: digits(fields[0], 2)?,
+        hour,
+        minute,
+        second,
+        weekday: Some(WEEKDAYS.iter().position(|day| *day == weekday)?),
+    })
+}
+
+/// `Sunday, 06-Nov-94 08:49:37 GMT`
+fn parse_rfc850(s: &str) -> Option<Parts> {
+    let (weekday, rest) = s.split_once(", ")?;
+    let fields: Vec<&str> = rest.split(' ').collect();
+    if fields.len() != 3 || fields[2] != "GMT" {
+        return None;
+    }
+
+    let date: Vec<&str> = fields[0].split('-').collect();
+    if date.len() != 3 {
+        return None;
+    }
+
+    // two digit years are in the 20th century from 70 on
+    let year = match digits(date[2], 2)? {
+        year @ 70.. => 1900 + year,
+        year => 2000 + year,
+    };
+    let (hour, minute, second) = parse_time(fields[1])?;
+    Some(Parts {
+        year,
+        month: month_of(date[1])?,
+        day: digits(date[0], 2)?,
+        hour,
+        minute,
+        second,
+        weekday: Some(LONG_WEEKDAYS.iter().position(|day| *day == weekday)?),
+    })
+}
+
+/// `Sun Nov  6 08:49:37 1994`
+fn parse_asctime(s: &str) -> Option<Parts> {
+    if s.len() != 24 || !s.is_ascii() {
+        return None;
+    }
+
+    let (hour, minute, second) = parse_time(&s[11..19])?;
+    let day = &s[8..10];
+    let day = match day.strip_prefix(' ') {
+        Some(single) => digits(single, 1)?,
+        None => digits(day, 2)?,
+    };
+    if &s[3..4] != " " || &s[7..8] != " " || &s[10..11] != " " || &s[19..20] != " " {
+        return None;
+    }
+
+    Some(Parts {
+        year: digits(&s[20..], 4)?,
+        month: month_of(&s[4..7])?,
+        day,
+        hour,
+        minute,
+        second,
+        weekday: Some(WEEKDAYS.iter().position(|wd| *wd == &s[..3])?),
+    })
+}
+
+fn is_leap_year(year: u64) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+/// Day of the week, 0 is Sunday, the epoch was a Thursday
+fn weekday_of(days: u64) -> usize {
+    ((days + 4) % 7) as usize
+}
+
+/// Days since the epoch of a date in the proleptic Gregorian calendar, year >= 1970
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    // shift the year to start in March, so the leap day is the last day
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let month = (month + 9) % 12;
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
+/// Inverse of `days_from_civil`
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(
+            HttpDate::from_unix_secs(0).to_string(),
+            "Thu, 01 Jan 1970 00:00:00 GMT"
+        );
+        assert_eq!(
+            HttpDate::from_unix_secs(784111777).to_string(),
+            "Sun, 06 Nov 1994 08:49:37 GMT"
+        );
+        assert_eq!(
+            HttpDate::from_unix_secs(951827696).to_string(),
+            "Tue, 29 Feb 2000 12:34:56 GMT"
+        );
+    }
+
+    #[test]
+    fn test_parse_date() {
+        let expected = HttpDate::from_unix_secs(784111777);
+        assert_eq!("Sun, 06 Nov 1994 08:49:37 GMT".parse(), Ok(expected));
+        assert_eq!("Sunday, 06-Nov-94 08:49:37 GMT".parse(), Ok(expected));
+        assert_eq!("Sun Nov  6 08:49:37 1994".parse(), Ok(expected));
+
+        assert!("Mon, 06 Nov 1994 08:49:37 GMT".parse::<HttpDate>().is_err());
+        assert!("Sun, 6 Nov 1994 08:49:37 GMT".parse::<HttpDate>().is_err());
+        assert!("Sun, 06 Nov 1994 08:49:37 UTC".parse::<HttpDate>().is_err());
+        assert!("Sun, 06 Nov 1994 24:00:00 GMT".parse::<HttpDate>().is_err());
+        assert!("Thu, 29 Feb 2001 00:00:00 GMT".parse::<HttpDate>().is_err());
+        assert!("Wed, 31 Dec 1969 23:59:59 GMT".parse::<HttpDate>().is_err());
+        assert!("".parse::<HttpDate>().is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for secs in [0, 59, 86399, 86400, 951782400, 1700000000, 4102444800] {
+            let date = HttpDate::from_unix_secs(secs);
+            assert_eq!(date.to_string().parse(), Ok(date));
+        }
+
+        let now = SystemTime::now();
+        let date = HttpDate::from(now);
+        let back = SystemTime::from(date);
+        assert!(now.duration_since(back).unwrap() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,365 @@
+use std::borrow::Cow;
+
+mod date;
+mod typed;
+
+pub use date::{HttpDate, InvalidDate};
+pub use typed::{
+    Accept, Authorization, ByteRange, CacheControl, ContentLength, ContentType, Date, ETag,
+    EntityTag, Host, IfModifiedSince, IfNoneMatch, InvalidHeader, LastModified, QualityItem, Range,
+    TypedHeader,
+};
+
+/// Well-known header names, in their canonical form
+///
+/// A name found here is stored without allocating.
+const STANDARD_NAMES: &[&str] = &[
+    "Accept",
+    "Accept-Charset",
+    "Accept-Encoding",
+    "Accept-Language",
+    "Accept-Ranges",
+    "Access-Control-Allow-Origin",
+    "Age",
+    "Allow",
+    "Authorization",
+    "Cache-Control",
+    "Connection",
+    "Content-Disposition",
+    "Content-Encoding",
+    "Content-Language",
+    "Content-Length",
+    "Content-Location",
+    "Content-Range",
+    "Content-Type",
+    "Cookie",
+    "Date",
+    "ETag",
+    "Expect",
+    "Expires",
+    "Forwarded",
+    "Host",
+    "If-Match",
+    "If-Modified-Since",
+    "If-None-Match",
+    "If-Range",
+    "If-Unmodified-Since",
+    "Keep-Alive",
+    "Last-Event-ID",
+    "Last-Modified",
+    "Location",
+    "Origin",
+    "Pragma",
+    "Range",
+    "Referer",
+    "Retry-After",
+    "Server",
+    "Set-Cookie",
+    "TE",
+    "Trailer",
+    "Transfer-Encoding",
+    "Upgrade",
+    "User-Agent",
+    "Vary",
+    "Via",
+    "WWW-Authenticate",
+    "X-Forwarded-For",
+    "X-Forwarded-Proto",
+];
+
+/// Header name, compared case-insensitively
+#[derive(Debug, Clone)]
+struct HeaderName(Cow<'static, str>);
+
+impl HeaderName {
+    fn new(name: &str) -> Self {
+        match STANDARD_NAMES
+            .iter()
+            .find(|standard| standard.eq_ignore_ascii_case(name))
+        {
+            Some(standard) => HeaderName(Cow::Borrowed(standard)),
+            None => HeaderName(Cow::Owned(name.to_string())),
+        }
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.0.eq_ignore_ascii_case(name)
+    }
+}
+
+impl PartialEq for HeaderName {
+    fn eq(&self, other: &Self) -> bool {
+        self.matches(&other.0)
+    }
+}
+
+impl Eq for HeaderName {}
+
+/// HTTP header map
+///
+/// Names are case-insensitive, a name can have several values and the order
+/// the headers were added in is kept.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct HttpHeaders {
+    /// Header key-value pairs, in wire order
+    entries: Vec<(HeaderName, String)>,
+}
+
+impl HttpHeaders {
+    pub fn new() -> Self {
+        HttpHeaders {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Insert to header, replacing all values of the same name
+    ///
+    /// The header keeps the position of the first replaced value.
+    pub fn insert(&mut self, k: &str, v: &str) {
+        match self.entries.iter().position(|(name, _)| name.matches(k)) {
+            Some(pos) => {
+                self.entries[pos].1 = v.to_string();
+
+                let mut index = 0;
+                self.entries.retain(|(name, _)| {
+                    index += 1;
+                    index - 1 <= pos || !name.matches(k)
+                });
+            }
+            None => self.append(k, v),
+        }
+    }
+
+    /// Add a value to header, keeping the existing values of the same name
+    pub fn append(&mut self, k: &str, v: &str) {
+        self.entries.push((HeaderName::new(k), v.to_string()));
+    }
+
+    /// Get the first value of the header
+    pub fn get(&self, k: &str) -> Option<&String> {
+        self.entries
+            .iter()
+            .find(|(name, _)| name.matches(k))
+            .map(|(_, value)| value)
+    }
+
+    /// Get all values of the header, in wire order
+    pub fn get_all<'a>(&'a self, k: &'a str) -> impl Iterator<Item = &'a String> {
+        self.entries
+            .iter()
+            .filter(move |(name, _)| name.matches(k))
+            .map(|(_, value)| value)
+    }
+
+    /// Get the elements of a comma-separated list header, such as `Connection`
+    ///
+    /// All values of the header are joined, see `split_list`.
+    pub fn get_list<'a>(&'a self, k: &'a str) -> Vec<&'a str> {
+        self.get_all(k)
+            .flat_map(|value| split_list(value))
+            .collect()
+    }
+
+    /// Check if a list header contains the token, ignoring case
+    pub fn has_token(&self, k: &str, token: &str) -> bool {
+        self.get_list(k)
+            .iter()
+            .any(|element| element.eq_ignore_ascii_case(token))
+    }
+
+    /// Remove all values of the header, return the first one
+    pub fn remove(&mut self, k: &str) -> Option<String> {
+        let mut removed = None;
+        self.entries.retain_mut(|(name, value)| {
+            if !name.matches(k) {
+                return true;
+            }
+            if removed.is_none() {
+                removed = Some(std::mem::take(value));
+            }
+            false
+        });
+
+        removed
+    }
+
+    /// Return an iterator over all headers, in wire order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(name, value)| (name.0.as_ref(), value.as_str()))
+    }
+
+    /// Check if the key in the header exists
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.entries.iter().any(|(name, _)| name.matches(key))
+    }
+
+    /// Number of header lines
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Parse a header into its typed form, `None` if it is missing or invalid
+    pub fn typed<H: TypedHeader>(&self) -> Option<H> {
+        self.try_typed().ok().flatten()
+    }
+
+    /// Parse a header into its typed form, `Ok(None)` if it is missing
+    pub fn try_typed<H: TypedHeader>(&self) -> Result<Option<H>, InvalidHeader> {
+        let values: Vec<&str> = self.get_all(H::NAME).map(String::as_str).collect();
+        match values.is_empty() {
+            true => Ok(None),
+            false => H::decode(&values).map(Some),
+        }
+    }
+
+    /// Render a typed header, replacing existing values
+    pub fn insert_typed<H: TypedHeader>(&mut self, header: &H) {
+        self.insert(H::NAME, &header.encode());
+    }
+}
+
+/// Directly used in for loop
+impl IntoIterator for HttpHeaders {
+    type Item = (String, String);
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self.entries.into_iter())
+    }
+}
+
+/// Owning iterator over the headers, in wire order
+pub struct IntoIter(std::vec::IntoIter<(HeaderName, String)>);
+
+impl Iterator for IntoIter {
+    type Item = (String, String);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0
+            .next()
+            .map(|(name, value)| (name.0.into_owned(), value))
+    }
+}
+
+/// Split a comma-separated list value, empty elements are skipped
+///
+/// Commas inside quoted strings don't split.
+pub fn split_list(value: &str) -> Vec<&str> {
+    let mut elements = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                elements.push(value[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    elements.push(value[start..].trim());
+
+    elements.retain(|element| !element.is_empty());
+    elements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::test;
+
+    #[test]
+    async fn test_header_add() {
+        let mut header = HttpHeaders::new();
+        header.insert("Content-Type", "Unknown");
+
+        assert!(header.contains_key("Content-Type"));
+        assert_eq!(header.get("Content-Type").unwrap(), "Unknown");
+    }
+
+    #[test]
+    async fn test_case_insensitive() {
+        let mut headers = HttpHeaders::new();
+        headers.insert("connection", "close");
+        headers.insert("X-Custom-Header", "1");
+
+        assert_eq!(headers.get("Connection").unwrap(), "close");
+        assert_eq!(headers.get("CONNECTION").unwrap(), "close");
+        assert!(headers.contains_key("x-custom-header"));
+
+        // well-known names are written in canonical form, others as given
+        let names: Vec<&str> = headers.iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["Connection", "X-Custom-Header"]);
+    }
+
+    #[test]
+    async fn test_append_and_insert() {
+        let mut headers = HttpHeaders::new();
+        headers.append("Set-Cookie", "a=1");
+        headers.append("Via", "1.1 proxy");
+        headers.append("set-cookie", "b=2");
+
+        assert_eq!(headers.get("Set-Cookie").unwrap(), "a=1");
+        assert_eq!(
+            headers.get_all("Set-Cookie").collect::<Vec<_>>(),
+            ["a=1", "b=2"]
+        );
+        assert_eq!(headers.len(), 3);
+
+        // insert replaces all values and keeps the first position
+        headers.insert("SET-COOKIE", "c=3");
+        assert_eq!(
+            headers.iter().collect::<Vec<_>>(),
+            [("Set-Cookie", "c=3"), ("Via", "1.1 proxy")]
+        );
+
+        assert_eq!(headers.remove("set-cookie").as_deref(), Some("c=3"));
+        assert_eq!(headers.remove("set-cookie"), None);
+        assert_eq!(headers.len(), 1);
+    }
+
+    #[test]
+    async fn test_wire_order() {
+        let mut headers = HttpHeaders::new();
+        for name in ["Zeta", "Host", "Alpha", "Content-Type", "Middle"] {
+            headers.append(name, "value");
+        }
+
+        let names: Vec<String> = headers.into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["Zeta", "Host", "Alpha", "Content-Type", "Middle"]);
+    }
+
+    #[test]
+    async fn test_list_values() {
+        assert_eq!(split_list("gzip, deflate ,br"), ["gzip", "deflate", "br"]);
+        assert_eq!(split_list(" , a,,b , "), ["a", "b"]);
+        assert_eq!(
+            split_list(r#"a="x, y", b="\"q,\"", c"#),
+            [r#"a="x, y""#, r#"b="\"q,\"""#, "c"]
+        );
+
+        let mut headers = HttpHeaders::new();
+        headers.append("Connection", "keep-alive, Upgrade");
+        headers.append("connection", "CLOSE");
+
+        assert_eq!(
+            headers.get_list("Connection"),
+            ["keep-alive", "Upgrade", "CLOSE"]
+        );
+        assert!(headers.has_token("Connection", "close"));
+        assert!(headers.has_token("Connection", "upgrade"));
+        assert!(!headers.has_token("Connection", "keep"));
+    }
+}
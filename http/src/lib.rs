@@ -1,16 +1,23 @@
 #![forbid(unsafe_code)]
 
 pub mod body;
+pub mod bytes;
 pub mod connect;
 pub mod error;
 pub mod feature;
+pub mod form;
+pub mod forwarded;
 pub mod handler;
 pub mod headers;
 pub mod method;
+pub mod multipart;
+pub mod proxy_protocol;
 pub mod request;
 pub mod response;
 pub mod router;
 pub mod server;
+pub mod status;
+pub mod stream;
 pub mod uri;
 pub mod utils;
 pub mod version;
@@ -2,6 +2,7 @@
 /// HTTP method
 pub enum HttpMethod {
     Get,
+    Head,
     Post,
     NoSupport,
 }
@@ -10,6 +11,7 @@ impl From<&str> for HttpMethod {
     fn from(value: &str) -> Self {
         match value {
             "GET" => HttpMethod::Get,
+            "HEAD" => HttpMethod::Head,
             "POST" => HttpMethod::Post,
             _ => HttpMethod::NoSupport,
         }
@@ -37,6 +39,7 @@ mod tests {
     #[test]
     fn test_method_into() {
         assert_eq!(HttpMethod::from("GET"), HttpMethod::Get);
+        assert_eq!(HttpMethod::from("HEAD"), HttpMethod::Head);
         assert_eq!(HttpMethod::from("POST"), HttpMethod::Post);
         assert_eq!(HttpMethod::from("some str"), HttpMethod::NoSupport);
 
@@ -0,0 +1,603 @@
+use tokio::io;
+
+use crate::{body::HttpBody, headers::HttpHeaders, request::HttpRequest, uri::percent_decode};
+
+#[derive(Debug)]
+pub enum MultipartError {
+    /// The request is not `multipart/form-data` or has no boundary
+    InvalidContentType(Option<String>),
+    /// A part is larger than `max_part_size`
+    PartTooLarge(usize),
+    /// The body is larger than `max_total_size`
+    TooLarge(usize),
+    /// The headers of a part are larger than `max_header_size`
+    HeadersTooLarge(usize),
+    /// The body doesn't follow the multipart format
+    Malformed(String),
+    /// The body ended before the close delimiter
+    Incomplete,
+    /// IO error while reading the body
+    IOError(io::Error),
+}
+
+impl From<io::Error> for MultipartError {
+    fn from(value: io::Error) -> Self {
+        MultipartError::IOError(value)
+    }
+}
+
+/// Size limits of a multipart body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MultipartLimits {
+    /// Maximum data size of a single part
+    pub max_part_size: usize,
+    /// Maximum size of the whole body, including delimiters and headers
+    pub max_total_size: usize,
+    /// Maximum size of the headers of a single part
+    pub max_header_size: usize,
+}
+
+impl Default for MultipartLimits {
+    fn default() -> Self {
+        MultipartLimits {
+            max_part_size: 1024 * 1024 * 1024,
+            max_total_size: 1024 * 1024 * 1024,
+            max_header_size: 8 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Before the first delimiter
+    Preamble,
+    /// Just after a delimiter, either a part or the end follows
+    Delimiter,
+    /// Reading the data of a part
+    Data,
+    /// After the close delimiter
+    End,
+}
+
+/// Streaming `multipart/form-data` reader
+///
+/// Parts are read one at a time and their data is streamed, nothing is buffered
+/// beyond a body chunk and a delimiter.
+pub struct Multipart {
+    /// Request body
+    body: HttpBody,
+    /// `\r\n--boundary`
+    delimiter: Vec<u8>,
+    /// Bytes read from the body but not parsed yet
+    buffer: Vec<u8>,
+    /// Parser state
+    state: State,
+    /// Size limits
+    limits: MultipartLimits,
+    /// Bytes read from the body
+    total_size: usize,
+    /// Data bytes of the current part
+    part_size: usize,
+}
+
+impl Multipart {
+    pub fn new(body: HttpBody, boundary: &str) -> Self {
+        Multipart {
+            body,
+            delimiter: format!("\r\n--{boundary}").into_bytes(),
+            // the first delimiter has no CRLF before it, pretend there is one
+            buffer: b"\r\n".to_vec(),
+            state: State::Preamble,
+            limits: MultipartLimits::default(),
+            total_size: 0,
+            part_size: 0,
+        }
+    }
+
+    /// Take the body of a `multipart/form-data` request
+    pub fn from_request(req: &mut HttpRequest) -> Result<Self, MultipartError> {
+        let content_type = req.headers.get("Content-Type");
+        let boundary = content_type.and_then(|value| parse_boundary(value));
+
+        match boundary {
+            Some(boundary) => {
+                let body = req.body.take().unwrap_or_default();
+                Ok(Multipart::new(body, &boundary))
+            }
+            None => Err(MultipartError::InvalidContentType(content_type.cloned())),
+        }
+    }
+
+    pub fn with_limits(mut self, limits: MultipartLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Return the next part, the unread data of the previous part is skipped
+    pub async fn next_part(&mut self) -> Result<Option<Part<'_>>, MultipartError> {
+        while self.state == State::Data {
+            self.read_data().await?;
+        }
+
+        if self.state == State::Preamble {
+            self.skip_preamble().await?;
+        }
+
+        if self.state == State::Delimiter {
+            self.read_delimiter_end().await?;
+        }
+
+        if self.state == State::End {
+            return Ok(None);
+        }
+
+        let headers = self.read_headers().await?;
+        let disposition = headers
+            .get("Content-Disposition")
+            .map(|value| parse_params(value))
+            .unwrap_or_default();
+        let content_type = headers.get("Content-Type").cloned();
+
+        self.state = State::Data;
+        self.part_size = 0;
+
+        Ok(Some(Part {
+            name: disposition_param(&disposition, "name"),
+            filename: disposition_param(&disposition, "filename"),
+            content_type,
+            headers,
+            multipart: self,
+        }))
+    }
+
+    /// Read the next chunk of the body, the body must not end here
+    async fn fill_buf(&mut self) -> Result<(), MultipartError> {
+        match self.body.read_next().await? {
+            Some(chunk) => {
+                self.total_size += chunk.len();
+                if self.total_size > self.limits.max_total_size {
+                    return Err(MultipartError::TooLarge(self.limits.max_total_size));
+                }
+
+                self.buffer.extend_from_slice(&chunk);
+                Ok(())
+            }
+            None => Err(MultipartError::Incomplete),
+        }
+    }
+
+    async fn skip_preamble(&mut self) -> Result<(), MultipartError> {
+        loop {
+            if let Some(pos) = find(&self.buffer, &self.delimiter) {
+                self.buffer.drain(..pos + self.delimiter.len());
+                self.state = State::Delimiter;
+                return Ok(());
+            }
+
+            // keep what could be the start of the delimiter
+            let keep = self.delimiter.len() - 1;
+            if self.buffer.len() > keep {
+                self.buffer.drain(..self.buffer.len() - keep);
+            }
+
+            self.fill_buf().await?;
+        }
+    }
+
+    /// After a delimiter comes `--` for the end, or padding and CRLF for a part
+    async fn read_delimiter_end(&mut self) -> Result<(), MultipartError> {
+        loop {
+            if self.buffer.starts_with(b"--") {
+                // the epilogue is ignored
+                self.state = State::End;
+                return Ok(());
+            }
+
+            if let Some(pos) = find(&self.buffer, b"\r\n") {
+                if !self.buffer[..pos].iter().all(|&b| b == b' ' || b == b'\t') {
+                    return Err(MultipartError::Malformed(
+                        "unexpected data after delimiter".to_string(),
+                    ));
+                }
+
+                self.buffer.drain(..pos + 2);
+                self.state = State::Data;
+                return Ok(());
+            }
+
+            if self.buffer.len() > self.limits.max_header_size {
+                return Err(MultipartError::Malformed(
+                    "unexpected data after delimiter".to_string(),
+                ));
+            }
+
+            self.fill_buf().await?;
+        }
+    }
+
+    async fn read_headers(&mut self) -> Result<HttpHeaders, MultipartError> {
+        let end = loop {
+            // a part without headers
+            if self.buffer.starts_with(b"\r\n") {
+                break 0;
+            }
+
+            if let Some(pos) = find(&self.buffer, b"\r\n\r\n") {
+                break pos + 2;
+            }
+
+            if self.buffer.len() > self.limits.max_header_size {
+                return Err(MultipartError::HeadersTooLarge(self.limits.max_header_size));
+            }
+
+            self.fill_buf().await?;
+        };
+
+        if end > self.limits.max_header_size {
+            return Err(MultipartError::HeadersTooLarge(self.limits.max_header_size));
+        }
+
+        let head: Vec<u8> = self.buffer.drain(..end + 2).collect();
+        let head = String::from_utf8_lossy(&head[..end]);
+
+        let mut headers = HttpHeaders::new();
+        for line in head.split("\r\n").filter(|line| !line.is_empty()) {
+            match line.split_once(':') {
+                Some((key, value)) => headers.append(key.trim(), value.trim()),
+                None => {
+                    return Err(MultipartError::Malformed(format!(
+                        "invalid part header: {line}"
+                    )));
+                }
+            }
+        }
+
+        Ok(headers)
+    }
+
+    /// Read the next chunk of data of the current part
+    async fn read_data(&mut self) -> Result<Option<Vec<u8>>, MultipartError> {
+        if self.state != State::Data {
+            return Ok(None);
+        }
+
+        let data: Vec<u8> = loop {
+            if let Some(pos) = find(&self.buffer, &self.delimiter) {
+                let data = self.buffer.drain(..pos).collect();
+                self.buffer.drain(..self.delimiter.len());
+                self.state = State::Delimiter;
+                break data;
+            }
+
+            // the end of the buffer could be the start of a delimiter split across chunks
+            let keep = self.delimiter.len() - 1;
+            if self.buffer.len() > keep {
+                break self.buffer.drain(..self.buffer.len() - keep).collect();
+            }
+
+            self.fill_buf().await?;
+        };
+
+        self.part_size += data.len();
+        if self.part_size > self.limits.max_part_size {
+            return Err(MultipartError::PartTooLarge(self.limits.max_part_size));
+        }
+
+        match data.is_empty() {
+            true => Ok(None),
+            false => Ok(Some(data)),
+        }
+    }
+}
+
+/// A single part of a multipart body
+pub struct Part<'a> {
+    /// `name` of the `Content-Disposition` header
+    name: Option<String>,
+    /// `filename` of the `Content-Disposition` header
+    filename: Option<String>,
+    /// `Content-Type` of the part
+    content_type: Option<String>,
+    /// All headers of the part
+    headers: HttpHeaders,
+    /// The reader this part belongs to
+    multipart: &'a mut Multipart,
+}
+
+impl Part<'_> {
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    pub fn headers(&self) -> &HttpHeaders {
+        &self.headers
+    }
+
+    /// Read the next chunk of data of this part
+    pub async fn read_next(&mut self) -> Result<Option<Vec<u8>>, MultipartError> {
+        self.multipart.read_data().await
+    }
+}
+
+impl std::fmt::Debug for Part<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Part")
+            .field("name", &self.name)
+            .field("filename", &self.filename)
+            .field("content_type", &self.content_type)
+            .field("headers", &self.headers)
+            .finish()
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Get the boundary of a `multipart/form-data` content type
+fn parse_boundary(content_type: &str) -> Option<String> {
+    let media_type = content_type.split(';').next().unwrap_or("").trim();
+    if !media_type.eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+
+    parse_params(content_type)
+        .into_iter()
+        .find(|(key, _)| key == "boundary")
+        .map(|(_, value)| value)
+        .filter(|boundary| !boundary.is_empty() && boundary.len() <= 70)
+}
+
+/// Parse `; key=value; key="quoted value"` parameters after the first `;`
+///
+/// Keys are lowercased.
+fn parse_params(value: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut rest = match value.split_once(';') {
+        Some((_, rest)) => rest,
+        None => return params,
+    };
+
+    loop {
+        rest = rest.trim_start_matches([' ', '\t', ';']);
+        let Some((key, after)) = rest.split_once('=') else {
+            break;
+        };
+        let key = key.trim().to_ascii_lowercase();
+
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let mut value = String::new();
+                let mut chars = quoted.char_indices();
+                let mut end = quoted.len();
+                while let Some((i, c)) = chars.next() {
+                    match c {
+                        '\\' => {
+                            if let Some((_, escaped)) = chars.next() {
+                                value.push(escaped);
+                            }
+                        }
+                        '"' => {
+                            end = i + 1;
+                            break;
+                        }
+                        c => value.push(c),
+                    }
+                }
+                (value, &quoted[end..])
+            }
+            None => {
+                let end = after.find(';').unwrap_or(after.len());
+                (after[..end].trim().to_string(), &after[end..])
+            }
+        };
+
+        params.push((key, value));
+        rest = after;
+    }
+
+    params
+}
+
+/// Get a `Content-Disposition` parameter, `key*` (RFC 5987) is preferred
+fn disposition_param(params: &[(String, String)], key: &str) -> Option<String> {
+    let extended = format!("{key}*");
+    let decoded = params
+        .iter()
+        .find(|(k, _)| *k == extended)
+        .and_then(|(_, value)| {
+            // charset'language'percent-encoded, only UTF-8 is supported
+            let (charset, rest) = value.split_once('\'')?;
+            let (_, encoded) = rest.split_once('\'')?;
+            match charset.eq_ignore_ascii_case("utf-8") {
+                true => String::from_utf8(percent_decode(encoded.as_bytes())).ok(),
+                false => None,
+            }
+        });
+
+    decoded.or_else(|| {
+        params
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.clone())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use tokio::test;
+
+    const BODY: &str = "preamble is ignored\r\n\
+                        --XyZ\r\n\
+                        Content-Disposition: form-data; name=\"title\"\r\n\
+                        \r\n\
+                        Hello\r\n\
+                        --XyZ  \r\n\
+                        Content-Disposition: form-data; name=\"file\"; filename=\"a \\\"b\\\".txt\"\r\n\
+                        Content-Type: text/plain\r\n\
+                        \r\n\
+                        line one\r\n--XyA is not a delimiter\r\n\
+                        --XyZ--\r\n\
+                        epilogue";
+
+    /// Read all parts, return (name, filename, content type, data)
+    async fn read_all(
+        multipart: &mut Multipart,
+    ) -> Result<Vec<(Option<String>, Option<String>, Option<String>, Vec<u8>)>, MultipartError>
+    {
+        let mut parts = Vec::new();
+        while let Some(mut part) = multipart.next_part().await? {
+            let mut data = Vec::new();
+            while let Some(chunk) = part.read_next().await? {
+                data.extend_from_slice(&chunk);
+            }
+
+            parts.push((
+                part.name().map(String::from),
+                part.filename().map(String::from),
+                part.content_type().map(String::from),
+                data,
+            ));
+        }
+
+        Ok(parts)
+    }
+
+    #[test]
+    async fn test_read_parts() {
+        // small chunks split the delimiter at every possible position
+        for chunk_size in [1, 2, 3, 5, 7, 8, 13, 1024] {
+            let body = HttpBody::from_reader(Cursor::new(BODY.as_bytes().to_vec()), chunk_size);
+            let mut multipart = Multipart::new(body, "XyZ");
+
+            let parts = read_all(&mut multipart).await.unwrap();
+            assert_eq!(parts.len(), 2, "chunk size {chunk_size}");
+
+            assert_eq!(parts[0].0.as_deref(), Some("title"));
+            assert_eq!(parts[0].1, None);
+            assert_eq!(parts[0].3, b"Hello");
+
+            assert_eq!(parts[1].0.as_deref(), Some("file"));
+            assert_eq!(parts[1].1.as_deref(), Some("a \"b\".txt"));
+            assert_eq!(parts[1].2.as_deref(), Some("text/plain"));
+            assert_eq!(parts[1].3, b"line one\r\n--XyA is not a delimiter");
+        }
+    }
+
+    #[test]
+    async fn test_skip_unread_part() {
+        let body = HttpBody::from_reader(Cursor::new(BODY.as_bytes().to_vec()), 4);
+        let mut multipart = Multipart::new(body, "XyZ");
+
+        let part = multipart.next_part().await.unwrap().unwrap();
+        assert_eq!(part.name(), Some("title"));
+
+        let mut part = multipart.next_part().await.unwrap().unwrap();
+        assert_eq!(part.name(), Some("file"));
+        let first = part.read_next().await.unwrap().unwrap();
+        assert!(b"line one\r\n--XyA is not a delimiter".starts_with(&first));
+
+        assert!(multipart.next_part().await.unwrap().is_none());
+    }
+
+    #[test]
+    async fn test_from_request() {
+        let mut req = HttpRequest::from(
+            "POST /upload HTTP/1.1\r\n\
+             Content-Type: multipart/form-data; boundary=\"XyZ\"\r\n\
+             \r\n",
+        );
+        req.body = Some(HttpBody::from(BODY));
+
+        let mut multipart = Multipart::from_request(&mut req).unwrap();
+        assert_eq!(read_all(&mut multipart).await.unwrap().len(), 2);
+
+        let mut req = HttpRequest::from(
+            "POST /upload HTTP/1.1\r\n\
+             Content-Type: multipart/form-data\r\n\
+             \r\n",
+        );
+        assert!(matches!(
+            Multipart::from_request(&mut req),
+            Err(MultipartError::InvalidContentType(Some(_)))
+        ));
+    }
+
+    #[test]
+    async fn test_limits() {
+        let body = HttpBody::from_reader(Cursor::new(BODY.as_bytes().to_vec()), 3);
+        let mut multipart = Multipart::new(body, "XyZ").with_limits(MultipartLimits {
+            max_part_size: 10,
+            ..MultipartLimits::default()
+        });
+        assert!(matches!(
+            read_all(&mut multipart).await,
+            Err(MultipartError::PartTooLarge(10))
+        ));
+
+        let body = HttpBody::from_reader(Cursor::new(BODY.as_bytes().to_vec()), 3);
+        let mut multipart = Multipart::new(body, "XyZ").with_limits(MultipartLimits {
+            max_total_size: 64,
+            ..MultipartLimits::default()
+        });
+        assert!(matches!(
+            read_all(&mut multipart).await,
+            Err(MultipartError::TooLarge(64))
+        ));
+
+        let body = HttpBody::from(BODY);
+        let mut multipart = Multipart::new(body, "XyZ").with_limits(MultipartLimits {
+            max_header_size: 16,
+            ..MultipartLimits::default()
+        });
+        assert!(matches!(
+            read_all(&mut multipart).await,
+            Err(MultipartError::HeadersTooLarge(16))
+        ));
+    }
+
+    #[test]
+    async fn test_incomplete_body() {
+        let body =
+            HttpBody::from("--XyZ\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\ndata");
+        let mut multipart = Multipart::new(body, "XyZ");
+        assert!(matches!(
+            read_all(&mut multipart).await,
+            Err(MultipartError::Incomplete)
+        ));
+    }
+
+    #[test]
+    async fn test_extended_filename() {
+        let params = parse_params(
+            "form-data; name=upload; filename=\"fallback.txt\"; filename*=UTF-8''%E4%BD%A0%E5%A5%BD.txt",
+        );
+        assert_eq!(
+            disposition_param(&params, "name").as_deref(),
+            Some("upload")
+        );
+        assert_eq!(
+            disposition_param(&params, "filename").as_deref(),
+            Some("你好.txt")
+        );
+
+        assert_eq!(
+            parse_boundary("Multipart/Form-Data; charset=utf-8; boundary=abc").as_deref(),
+            Some("abc")
+        );
+        assert_eq!(parse_boundary("text/plain; boundary=abc"), None);
+    }
+}
@@ -0,0 +1,358 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str,
+};
+
+use crate::error::ServerError;
+
+/// Starts every v2 header
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+/// Starts every v1 header
+const V1_PREFIX: &[u8] = b"PROXY ";
+/// Longest v1 header, with the CRLF
+const V1_MAX_LENGTH: usize = 107;
+/// Signature, version and command, family and length
+const V2_HEADER_LENGTH: usize = 16;
+
+/// Application protocol negotiated with the client, such as `h2`
+pub const TLV_ALPN: u8 = 0x01;
+/// Host name the client asked for, such as the TLS SNI
+pub const TLV_AUTHORITY: u8 = 0x02;
+/// An id of the connection assigned by the proxy
+pub const TLV_UNIQUE_ID: u8 = 0x05;
+/// TLS details of the client connection
+pub const TLV_SSL: u8 = 0x20;
+/// `TLV_SSL` flag of a client connected over TLS
+const PP2_CLIENT_SSL: u8 = 0x01;
+
+/// A PROXY protocol header, sent by a load balancer before the client's bytes
+///
+/// See the HAProxy PROXY protocol specification. The CRC32C TLV is not verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyHeader {
+    /// 1 for the text form, 2 for the binary form
+    pub version: u8,
+    /// Address of the client, `None` for `UNKNOWN`, `LOCAL` and non-IP families
+    pub source: Option<SocketAddr>,
+    /// Address the client connected to
+    pub destination: Option<SocketAddr>,
+    /// Type-length-value fields of a v2 header, in wire order
+    pub tlvs: Vec<Tlv>,
+}
+
+/// A type-length-value field of a v2 header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tlv {
+    pub kind: u8,
+    pub value: Vec<u8>,
+}
+
+impl ProxyHeader {
+    /// Parse a header from the start of `buf`, return it and its length
+    ///
+    /// `Ok(None)` means the header is not complete yet. Bytes which can't
+    /// start a header are an error, the header is required.
+    pub fn parse(buf: &[u8]) -> Result<Option<(Self, usize)>, ServerError> {
+        if buf.starts_with(&V2_SIGNATURE) {
+            parse_v2(buf)
+        } else if buf.starts_with(V1_PREFIX) {
+            parse_v1(buf)
+        } else if V2_SIGNATURE.starts_with(buf) || V1_PREFIX.starts_with(buf) {
+            Ok(None)
+        } else {
+            Err(invalid("missing PROXY protocol header"))
+        }
+    }
+
+    /// The value of the first TLV of `kind`
+    pub fn tlv(&self, kind: u8) -> Option<&[u8]> {
+        self.tlvs
+            .iter()
+            .find(|tlv| tlv.kind == kind)
+            .map(|tlv| tlv.value.as_slice())
+    }
+
+    /// The host name the client asked for
+    pub fn authority(&self) -> Option<&str> {
+        self.tlv(TLV_AUTHORITY)
+            .and_then(|value| str::from_utf8(value).ok())
+    }
+
+    /// Whether the client connected to the proxy over TLS
+    pub fn is_tls(&self) -> bool {
+        self.tlv(TLV_SSL).is_some_and(|value| {
+            value
+                .first()
+                .is_some_and(|flags| flags & PP2_CLIENT_SSL != 0)
+        })
+    }
+}
+
+fn invalid(message: &str) -> ServerError {
+    ServerError::ProtocolError(format!("PROXY protocol: {message}"))
+}
+
+/// `PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n`
+fn parse_v1(buf: &[u8]) -> Result<Option<(ProxyHeader, usize)>, ServerError> {
+    let Some(end) = buf
+        .windows(2)
+        .take(V1_MAX_LENGTH - 1)
+        .position(|w| w == b"\r\n")
+    else {
+        return match buf.len() >= V1_MAX_LENGTH {
+            true => Err(invalid("v1 header is too long")),
+            false => Ok(None),
+        };
+    };
+    let line = str::from_utf8(&buf[..end]).map_err(|_| invalid("v1 header is not ASCII"))?;
+
+    let parts: Vec<&str> = line.split(' ').collect();
+    let (source, destination) = match parts.as_slice() {
+        // the proxy doesn't know the client, the rest of the line is ignored
+        ["PROXY", "UNKNOWN", ..] => (None, None),
+        [
+            "PROXY",
+            family @ ("TCP4" | "TCP6"),
+            source,
+            destination,
+            source_port,
+            destination_port,
+        ] => {
+            let parse_ip = |ip: &str| -> Result<IpAddr, ServerError> {
+                let ip = match *family {
+                    "TCP4" => ip.parse::<Ipv4Addr>().map(IpAddr::V4),
+                    _ => ip.parse::<Ipv6Addr>().map(IpAddr::V6),
+                };
+                ip.map_err(|_| invalid("invalid v1 address"))
+            };
+            let parse_port =
+                |port: &str| port.parse::<u16>().map_err(|_| invalid("invalid v1 port"));
+
+            (
+                Some(SocketAddr::new(parse_ip(source)?, parse_port(source_port)?)),
+                Some(SocketAddr::new(
+                    parse_ip(destination)?,
+                    parse_port(destination_port)?,
+                )),
+            )
+        }
+        _ => return Err(invalid("invalid v1 header")),
+    };
+
+    let header = ProxyHeader {
+        version: 1,
+        source,
+        destination,
+        tlvs: Vec::new(),
+    };
+    Ok(Some((header, end + 2)))
+}
+
+/// Binary header, followed by the addresses and the TLVs
+fn parse_v2(buf: &[u8]) -> Result<Option<(ProxyHeader, usize)>, ServerError> {
+    if buf.len() < V2_HEADER_LENGTH {
+        return Ok(None);
+    }
+
+    let version_command = buf[12];
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported version"));
+    }
+    // LOCAL is a connection of the proxy itself, such as a health check
+    let local = match version_command & 0x0f {
+        0x0 => true,
+        0x1 => false,
+        _ => return Err(invalid("unsupported command")),
+    };
+
+    let length = u16::from_be_bytes([buf[14], buf[15]]) as usize;
+    let Some(body) = buf.get(V2_HEADER_LENGTH..V2_HEADER_LENGTH + length) else {
+        return Ok(None);
+    };
+
+    let (addresses, tlvs) = match buf[13] {
+        // UNSPEC
+        0x00 => (None, body),
+        // TCP over IPv4
+        0x11 if body.len() >= 12 => {
+            let ip = |at: usize| IpAddr::from(<[u8; 4]>::try_from(&body[at..at + 4]).unwrap());
+            let port = |at: usize| u16::from_be_bytes([body[at], body[at + 1]]);
+            let addresses = (
+                SocketAddr::new(ip(0), port(8)),
+                SocketAddr::new(ip(4), port(10)),
+            );
+            (Some(addresses), &body[12..])
+        }
+        // TCP over IPv6
+        0x21 if body.len() >= 36 => {
+            let ip = |at: usize| IpAddr::from(<[u8; 16]>::try_from(&body[at..at + 16]).unwrap());
+            let port = |at: usize| u16::from_be_bytes([body[at], body[at + 1]]);
+            let addresses = (
+                SocketAddr::new(ip(0), port(32)),
+                SocketAddr::new(ip(16), port(34)),
+            );
+            (Some(addresses), &body[36..])
+        }
+        // stream over Unix sockets, the paths are of no use
+        0x31 if body.len() >= 216 => (None, &body[216..]),
+        0x11 | 0x21 | 0x31 => return Err(invalid("v2 addresses are truncated")),
+        _ => return Err(invalid("unsupported address family")),
+    };
+    let (source, destination) = match addresses {
+        Some((source, destination)) if !local => (Some(source), Some(destination)),
+        _ => (None, None),
+    };
+
+    let header = ProxyHeader {
+        version: 2,
+        source,
+        destination,
+        tlvs: parse_tlvs(tlvs)?,
+    };
+    Ok(Some((header, V2_HEADER_LENGTH + length)))
+}
+
+fn parse_tlvs(mut buf: &[u8]) -> Result<Vec<Tlv>, ServerError> {
+    let mut tlvs = Vec::new();
+    while !buf.is_empty() {
+        let [kind, high, low, rest @ ..] = buf else {
+            return Err(invalid("truncated TLV"));
+        };
+        let length = u16::from_be_bytes([*high, *low]) as usize;
+        if rest.len() < length {
+            return Err(invalid("truncated TLV"));
+        }
+
+        tlvs.push(Tlv {
+            kind: *kind,
+            value: rest[..length].to_vec(),
+        });
+        buf = &rest[length..];
+    }
+
+    Ok(tlvs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A v2 header of a TCP over IPv4 connection
+    fn v2_header(tlvs: &[(u8, &[u8])]) -> Vec<u8> {
+        let mut body = vec![192, 0, 2, 1, 198, 51, 100, 1];
+        body.extend_from_slice(&56324u16.to_be_bytes());
+        body.extend_from_slice(&443u16.to_be_bytes());
+        for (kind, value) in tlvs {
+            body.push(*kind);
+            body.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            body.extend_from_slice(value);
+        }
+
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x21, 0x11]);
+        header.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        header.extend_from_slice(&body);
+        header
+    }
+
+    #[test]
+    fn test_v1() {
+        let buf = b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\nGET / HTTP/1.1\r\n";
+        let (header, length) = ProxyHeader::parse(buf).unwrap().unwrap();
+        assert_eq!(header.version, 1);
+        assert_eq!(header.source, Some("192.0.2.1:56324".parse().unwrap()));
+        assert_eq!(
+            header.destination,
+            Some("198.51.100.1:443".parse().unwrap())
+        );
+        assert_eq!(&buf[length..], b"GET / HTTP/1.1\r\n");
+
+        let buf = b"PROXY TCP6 2001:db8::1 2001:db8::2 4711 80\r\n";
+        let (header, _) = ProxyHeader::parse(buf).unwrap().unwrap();
+        assert_eq!(header.source, Some("[2001:db8::1]:4711".parse().unwrap()));
+
+        let (header, length) = ProxyHeader::parse(b"PROXY UNKNOWN whatever\r\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!((header.source, length), (None, 24));
+    }
+
+    #[test]
+    fn test_v1_invalid() {
+        // not complete yet
+        assert!(ProxyHeader::parse(b"").unwrap().is_none());
+        assert!(ProxyHeader::parse(b"PRO").unwrap().is_none());
+        assert!(
+            ProxyHeader::parse(b"PROXY TCP4 192.0.2.1")
+                .unwrap()
+                .is_none()
+        );
+
+        for buf in [
+            &b"GET / HTTP/1.1\r\n\r\n"[..],
+            b"PROXY TCP4 2001:db8::1 192.0.2.2 1 2\r\n",
+            b"PROXY TCP4 192.0.2.1 192.0.2.2 1\r\n",
+            b"PROXY TCP4 192.0.2.1 192.0.2.2 1 70000\r\n",
+            b"PROXY UDP4 192.0.2.1 192.0.2.2 1 2\r\n",
+            &b"PROXY x".repeat(20),
+        ] {
+            assert!(ProxyHeader::parse(buf).is_err(), "{buf:?}");
+        }
+    }
+
+    #[test]
+    fn test_v2() {
+        let mut buf = v2_header(&[
+            (TLV_AUTHORITY, b"example.com"),
+            (TLV_SSL, &[0x01, 0, 0, 0, 0]),
+        ]);
+        let header_length = buf.len();
+        buf.extend_from_slice(b"GET / HTTP/1.1\r\n");
+
+        let (header, length) = ProxyHeader::parse(&buf).unwrap().unwrap();
+        assert_eq!(length, header_length);
+        assert_eq!(header.version, 2);
+        assert_eq!(header.source, Some("192.0.2.1:56324".parse().unwrap()));
+        assert_eq!(
+            header.destination,
+            Some("198.51.100.1:443".parse().unwrap())
+        );
+        assert_eq!(header.authority(), Some("example.com"));
+        assert!(header.is_tls());
+        assert_eq!(header.tlv(TLV_UNIQUE_ID), None);
+
+        // not complete yet
+        for end in [5, 15, header_length - 1] {
+            assert!(ProxyHeader::parse(&buf[..end]).unwrap().is_none());
+        }
+
+        // a health check of the proxy keeps the real addresses
+        let mut local = v2_header(&[]);
+        local[12] = 0x20;
+        let (header, _) = ProxyHeader::parse(&local).unwrap().unwrap();
+        assert_eq!(header.source, None);
+    }
+
+    #[test]
+    fn test_v2_invalid() {
+        let valid = v2_header(&[(TLV_ALPN, b"h2")]);
+
+        let mut version = valid.clone();
+        version[12] = 0x11;
+        let mut command = valid.clone();
+        command[12] = 0x2f;
+        let mut family = valid.clone();
+        family[13] = 0x12;
+        // the TLV claims more bytes than the header has
+        let mut tlv = valid.clone();
+        let at = tlv.len() - 3;
+        tlv[at] = 0x10;
+        // the length doesn't cover the addresses
+        let mut addresses = valid[..V2_HEADER_LENGTH + 4].to_vec();
+        addresses[15] = 4;
+
+        for buf in [version, command, family, tlv, addresses] {
+            assert!(ProxyHeader::parse(&buf).is_err(), "{buf:?}");
+        }
+    }
+}
@@ -3,8 +3,8 @@ use std::pin::Pin;
 use tokio::io::{AsyncRead, AsyncReadExt};
 
 use crate::{
-    body::HttpBody, error::ServerError, headers::HttpHeaders, method::HttpMethod, uri::HttpUri,
-    utils, version::HttpVersion,
+    body::HttpBody, connect::ConnectionInfo, error::ServerError, headers::HttpHeaders,
+    method::HttpMethod, uri::HttpUri, utils, version::HttpVersion,
 };
 
 #[derive(Debug)]
@@ -19,6 +19,8 @@ pub struct HttpRequest {
     pub uri: HttpUri,
     /// HTTP version
     pub version: HttpVersion,
+    /// The connection the request arrived on, empty outside of a server
+    pub connection: ConnectionInfo,
 }
 
 impl From<String> for HttpRequest {
@@ -59,7 +61,7 @@ impl From<&str> for HttpRequest {
                 header_lines += 1;
                 let parts: Vec<&str> = line.split(':').collect();
                 if parts.len() == 2 {
-                    parsed_headers.insert(parts[0].trim(), parts[1].trim());
+                    parsed_headers.append(parts[0].trim(), parts[1].trim());
                 }
             }
         }
@@ -79,6 +81,7 @@ impl From<&str> for HttpRequest {
             body: Some(parsed_body),
             uri: parsed_uri,
             version: parsed_version,
+            connection: ConnectionInfo::default(),
         }
     }
 }
@@ -127,6 +130,7 @@ impl HttpRequest {
                     reader: remaining_stream,
                     read_buf: pre_read,
                     buffer_size: 1024,
+                    length: None,
                 }
             } else {
                 HttpBody::Empty
@@ -139,6 +143,21 @@ impl HttpRequest {
             body: Some(body),
             uri,
             version,
+            connection: ConnectionInfo::default(),
+        })
+    }
+
+    /// Parse the request line and headers, the body is attached later
+    pub(crate) fn from_head(head: &str) -> Result<Self, ServerError> {
+        let (method, uri, version, headers) = Self::parse_headers(head)?;
+
+        Ok(HttpRequest {
+            method,
+            headers,
+            body: Some(HttpBody::Empty),
+            uri,
+            version,
+            connection: ConnectionInfo::default(),
         })
     }
 
@@ -158,7 +177,7 @@ impl HttpRequest {
             ));
         }
         let method = HttpMethod::from(request_line_parts[0]);
-        let uri = HttpUri::from(request_line_parts[1]);
+        let uri = HttpUri::parse(request_line_parts[1])?;
         let version = HttpVersion::from(request_line_parts[2]);
 
         // headers
@@ -172,7 +191,7 @@ impl HttpRequest {
             // Split the line into key and value
             let parts: Vec<&str> = line.splitn(2, ':').collect();
             if parts.len() == 2 {
-                headers.insert(parts[0].trim(), parts[1].trim());
+                headers.append(parts[0].trim(), parts[1].trim());
             }
         }
 
@@ -208,9 +227,31 @@ mod tests {
             HttpBody::Empty => (),
             HttpBody::InMemory { data } => assert!(data.is_empty()),
             HttpBody::Streaming { .. } => panic!("Expected InMemory or Empty body, got Streaming"),
+            HttpBody::Incoming(_) => panic!("Expected InMemory or Empty body, got Incoming"),
+            HttpBody::Stream { .. } => panic!("Expected InMemory or Empty body, got Stream"),
         }
     }
 
+    #[test]
+    async fn test_repeated_headers() {
+        let request = HttpRequest::from_head(
+            "GET / HTTP/1.1\r\n\
+             Host: gsgfs.moe\r\n\
+             Via: 1.1 first\r\n\
+             accept-encoding: gzip\r\n\
+             VIA: 1.1 second\r\n\
+             \r\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            request.headers.get_all("via").collect::<Vec<_>>(),
+            ["1.1 first", "1.1 second"]
+        );
+        assert_eq!(request.headers.get("Accept-Encoding").unwrap(), "gzip");
+        assert_eq!(request.headers.len(), 4);
+    }
+
     #[test]
     async fn test_empty_request() {
         let request_string = "GET / HTTP/1.1";
@@ -278,6 +319,7 @@ mod tests {
                 mut read_buf,
                 buffer_size,
                 mut reader,
+                ..
             } = http_body
             {
                 let mut content = Vec::new();
@@ -1,13 +1,28 @@
+use std::io::{IoSlice, Write as _};
+
 use tokio::io::{self, AsyncRead, AsyncWrite, AsyncWriteExt};
 
-use crate::{body::HttpBody, headers::HttpHeaders, version::HttpVersion};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+#[cfg(feature = "serde")]
+use crate::error::ServerError;
+use crate::{
+    body::HttpBody,
+    headers::{ContentLength, ContentType, HttpHeaders, TypedHeader},
+    method::HttpMethod,
+    status::StatusCode,
+    uri::{EncodeSet, percent_encode},
+    utils::get_content_type,
+    version::HttpVersion,
+};
 
 #[derive(Debug)]
 pub struct HttpResponse {
     /// HTTP status code
-    status_code: u16,
-    /// HTTP status text
-    status_text: String,
+    status: StatusCode,
+    /// Custom reason phrase, the canonical one is used if `None`
+    reason: Option<String>,
     /// HTTP headers
     headers: HttpHeaders,
     /// HTTP body
@@ -19,10 +34,10 @@ pub struct HttpResponse {
 }
 
 impl HttpResponse {
-    pub fn new(status_code: u16, status_text: &str) -> Self {
+    pub fn new(status: StatusCode) -> Self {
         HttpResponse {
-            status_code,
-            status_text: status_text.to_string(),
+            status,
+            reason: None,
             headers: HttpHeaders::new(),
             body: HttpBody::new(),
             version: HttpVersion::V1_1,
@@ -30,8 +45,103 @@ impl HttpResponse {
         }
     }
 
+    /// `200 OK` with a `text/plain; charset=utf-8` body
+    pub fn text(body: impl Into<String>) -> Self {
+        HttpResponse::with_content(ContentType::text(), body.into().into_bytes())
+    }
+
+    /// `200 OK` with a `text/html; charset=utf-8` body
+    pub fn html(body: impl Into<String>) -> Self {
+        HttpResponse::with_content(ContentType::html(), body.into().into_bytes())
+    }
+
+    /// `200 OK` with `value` serialized as an `application/json` body
+    #[cfg(feature = "serde")]
+    pub fn json<T: Serialize + ?Sized>(value: &T) -> Result<Self, ServerError> {
+        let data = serde_json::to_vec(value)
+            .map_err(|e| ServerError::InternalError(format!("failed to serialize json: {e}")))?;
+
+        Ok(HttpResponse::with_content(ContentType::json(), data))
+    }
+
+    /// Redirect to `location` with an empty body
+    ///
+    /// `status` should be a 3xx code, such as `StatusCode::SEE_OTHER`.
+    pub fn redirect(status: StatusCode, location: &str) -> Self {
+        HttpResponse::new(status)
+            .with_body(HttpBody::from(""))
+            .insert_header("Location", location)
+    }
+
+    /// `204 No Content`
+    pub fn no_content() -> Self {
+        HttpResponse::new(StatusCode::NO_CONTENT)
+    }
+
+    /// `304 Not Modified`
+    pub fn not_modified() -> Self {
+        HttpResponse::new(StatusCode::NOT_MODIFIED)
+    }
+
+    /// `200 OK` which the browser saves as `filename`
+    ///
+    /// The content type is guessed from the extension. Non-ASCII names are sent
+    /// both as an ASCII fallback and as an RFC 5987 `filename*`.
+    pub fn attachment(filename: &str, body: HttpBody) -> Self {
+        let mut content_type = ContentType::new(get_content_type(filename));
+        if content_type.media_type().starts_with("text/") {
+            content_type = content_type.with_param("charset", "utf-8");
+        }
+
+        HttpResponse::new(StatusCode::OK)
+            .with_body(body)
+            .insert_header("Content-Type", &content_type.encode())
+            .insert_header("Content-Disposition", &content_disposition(filename))
+    }
+
+    fn with_content(content_type: ContentType, data: Vec<u8>) -> Self {
+        HttpResponse::new(StatusCode::OK)
+            .with_body(HttpBody::from_data(data))
+            .insert_header("Content-Type", &content_type.encode())
+    }
+
+    /// Use a custom reason phrase, control characters are removed
+    pub fn with_reason(mut self, reason: &str) -> Self {
+        self.reason = Some(
+            reason
+                .chars()
+                .filter(|c| *c == '\t' || !c.is_control())
+                .collect(),
+        );
+        self
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Change the status, a custom reason phrase is dropped
+    pub fn set_status(&mut self, status: StatusCode) -> &mut Self {
+        self.status = status;
+        self.reason = None;
+        self
+    }
+
+    /// The reason phrase sent in the status line, may be empty
+    pub fn reason(&self) -> &str {
+        match &self.reason {
+            Some(reason) => reason,
+            None => self.status.canonical_reason().unwrap_or(""),
+        }
+    }
+
+    pub fn version(&self) -> &HttpVersion {
+        &self.version
+    }
+
+    /// Set the body, a streaming body of unknown length is sent chunked
     pub fn with_body(mut self, body: HttpBody) -> Self {
-        self.body = body;
+        self.set_body(body);
         self
     }
 
@@ -46,10 +156,27 @@ impl HttpResponse {
     }
 
     pub fn add_body(&mut self, body: HttpBody) -> &mut Self {
-        self.body = body;
+        self.set_body(body);
         self
     }
 
+    fn set_body(&mut self, body: HttpBody) {
+        let chunked = body.is_streaming() && body.content_length().is_none();
+        if chunked {
+            self.headers.insert("Transfer-Encoding", "chunked");
+        } else if self.chunked_encoding {
+            self.headers.remove("Transfer-Encoding");
+        }
+
+        self.body = body;
+        self.chunked_encoding = chunked;
+    }
+
+    pub fn headers(&self) -> &HttpHeaders {
+        &self.headers
+    }
+
+    /// Same as `headers`
     pub fn handlers(&self) -> &HttpHeaders {
         &self.headers
     }
@@ -78,86 +205,278 @@ impl HttpResponse {
         self
     }
 
-    async fn write_headers<W>(&self, writer: &mut W) -> io::Result<()>
+    /// Stream exactly `length` bytes from `reader`, sent with `Content-Length`
+    ///
+    /// Sending fails if the reader ends early or yields more than `length` bytes.
+    pub fn with_sized_streaming_body<R>(
+        mut self,
+        reader: R,
+        length: usize,
+        buffer_size: usize,
+    ) -> Self
     where
-        W: AsyncWrite + Unpin,
+        R: AsyncRead + Send + Sync + 'static,
     {
-        let header = format!(
+        self.body = HttpBody::from_sized_reader(reader, length, buffer_size);
+
+        self.chunked_encoding = false;
+        self.headers.remove("Transfer-Encoding");
+        self.headers.insert("Content-Length", &length.to_string());
+
+        self
+    }
+
+    /// Whether the status allows a body, see RFC 9112 section 6.3
+    fn status_allows_body(&self) -> bool {
+        !(self.status.is_informational()
+            || self.status == StatusCode::NO_CONTENT
+            || self.status == StatusCode::NOT_MODIFIED)
+    }
+
+    /// Whether the status forbids `Content-Length` and `Transfer-Encoding`
+    fn status_forbids_framing(&self) -> bool {
+        self.status.is_informational() || self.status == StatusCode::NO_CONTENT
+    }
+
+    /// Serialize the status line and headers into `buf`
+    ///
+    /// A header with CR, LF or NUL would split the response and is refused.
+    fn encode_head(&self, buf: &mut Vec<u8>, method: HttpMethod) -> io::Result<()> {
+        let _ = write!(
+            buf,
             "{} {} {}\r\n",
-            self.version.as_string(),
-            self.status_code,
-            self.status_text
+            self.version.as_str(),
+            self.status,
+            self.reason()
         );
-        writer.write_all(header.as_bytes()).await?;
 
         // if not chunked, add content length
+        // a 304 has the length of the representation, so only an explicit one is sent
+        let length = match self.body {
+            HttpBody::Empty if method != HttpMethod::Head => Some(0),
+            _ => self.body.content_length(),
+        };
         if !self.chunked_encoding
-            && let Some(length) = self.body.content_length()
+            && self.status_allows_body()
+            && let Some(length) = length
             && !self.headers.contains_key("Content-Length")
         {
-            let header = format!("Content-Length: {length}\r\n");
-            writer.write_all(header.as_bytes()).await?;
+            let _ = write!(buf, "Content-Length: {length}\r\n");
         }
 
+        let forbids_framing = self.status_forbids_framing();
         for (key, value) in self.headers.iter() {
-            let header_line = format!("{key}: {value}\r\n");
-            writer.write_all(header_line.as_bytes()).await?;
+            if forbids_framing
+                && (key.eq_ignore_ascii_case("Content-Length")
+                    || key.eq_ignore_ascii_case("Transfer-Encoding"))
+            {
+                continue;
+            }
+            if [key, value]
+                .iter()
+                .any(|part| part.contains(['\r', '\n', '\0']))
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid character in header {key:?}"),
+                ));
+            }
+
+            buf.extend_from_slice(key.as_bytes());
+            buf.extend_from_slice(b": ");
+            buf.extend_from_slice(value.as_bytes());
+            buf.extend_from_slice(b"\r\n");
         }
 
         // end of headers
-        writer.write_all(b"\r\n").await?;
-
+        buf.extend_from_slice(b"\r\n");
         Ok(())
     }
 
-    /// send response
-    pub async fn send<W>(&mut self, writer: &mut W) -> io::Result<()>
+    /// Send the response to a request with `method`
+    ///
+    /// The body is left out for `HEAD` requests and for 1xx, 204 and 304
+    /// responses. `HEAD` responses keep the length headers of the body.
+    pub async fn send<W>(&mut self, writer: &mut W, method: HttpMethod) -> io::Result<()>
     where
         W: AsyncWrite + Unpin,
     {
-        self.write_headers(writer).await?;
+        self.send_with_buffer(writer, method, &mut Vec::new()).await
+    }
+
+    /// Same as `send`, but serialize the head into `buf` so it can be reused
+    ///
+    /// The head goes out in the same write as the first part of the body.
+    pub async fn send_with_buffer<W>(
+        &mut self,
+        writer: &mut W,
+        method: HttpMethod,
+        buf: &mut Vec<u8>,
+    ) -> io::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        buf.clear();
+        self.encode_head(buf, method)?;
+
+        if method == HttpMethod::Head || !self.status_allows_body() {
+            writer.write_all(buf).await?;
+            writer.flush().await?;
+            return Ok(());
+        }
 
         match self.chunked_encoding {
-            true => self.send_chunked(writer).await?,
-            false => self.send_normal(writer).await?,
+            true => self.send_chunked(writer, buf).await?,
+            false => self.send_normal(writer, buf).await?,
         }
 
         Ok(())
     }
 
-    async fn send_normal<W>(&mut self, writer: &mut W) -> io::Result<()>
+    /// Send the head in `buf` and the body as is
+    ///
+    /// The body must match the `Content-Length` if there is one.
+    async fn send_normal<W>(&mut self, writer: &mut W, buf: &mut Vec<u8>) -> io::Result<()>
     where
         W: AsyncWrite + Unpin,
     {
+        let declared = match self.headers.try_typed::<ContentLength>() {
+            Ok(Some(ContentLength(length))) => Some(length),
+            Ok(None) => self.body.content_length().map(|length| length as u64),
+            Err(e) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid Content-Length: {:?}", e.value),
+                ));
+            }
+        };
+
+        let mut sent = 0u64;
         while let Some(chunk) = self.body.read_next().await? {
-            writer.write_all(&chunk).await?;
+            sent += chunk.len() as u64;
+            if let Some(declared) = declared
+                && sent > declared
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("body is longer than the declared {declared} bytes"),
+                ));
+            }
+
+            if chunk.is_empty() {
+                flush_pending(writer, buf).await?;
+                continue;
+            }
+
+            // the head is still pending before the first chunk
+            match buf.is_empty() {
+                true => writer.write_all(&chunk).await?,
+                false => {
+                    write_all_vectored(writer, &mut [IoSlice::new(buf), IoSlice::new(&chunk)])
+                        .await?;
+                    buf.clear();
+                }
+            }
+        }
+
+        if let Some(declared) = declared
+            && sent < declared
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("body ended after {sent} of the declared {declared} bytes"),
+            ));
         }
+
+        writer.write_all(buf).await?;
         writer.flush().await?;
 
         Ok(())
     }
 
-    async fn send_chunked<W>(&mut self, writer: &mut W) -> io::Result<()>
+    /// Send the head in `buf` and the body in chunks, one write per chunk
+    async fn send_chunked<W>(&mut self, writer: &mut W, buf: &mut Vec<u8>) -> io::Result<()>
     where
         W: AsyncWrite + Unpin,
     {
         while let Some(chunk) = self.body.read_next().await? {
-            if !chunk.is_empty() {
-                let content = format!("{:X}\r\n", chunk.len());
-                writer.write_all(content.as_bytes()).await?;
-                writer.write_all(&chunk).await?;
-                writer.write_all(b"\r\n").await?;
+            if chunk.is_empty() {
+                flush_pending(writer, buf).await?;
+            } else {
+                // the size line follows the head or is sent alone
+                let _ = write!(buf, "{:X}\r\n", chunk.len());
+                write_all_vectored(
+                    writer,
+                    &mut [
+                        IoSlice::new(buf),
+                        IoSlice::new(&chunk),
+                        IoSlice::new(b"\r\n"),
+                    ],
+                )
+                .await?;
+                buf.clear();
             }
         }
 
-        writer.write_all(b"0\r\n").await?;
-        writer.write_all(b"\r\n").await?;
+        buf.extend_from_slice(b"0\r\n\r\n");
+        writer.write_all(buf).await?;
         writer.flush().await?;
 
         Ok(())
     }
 }
 
+/// Write out `buf`, such as a head still waiting for the first chunk, and flush
+///
+/// Bodies yield an empty chunk to ask for a flush, see `HttpBody::channel`.
+async fn flush_pending<W>(writer: &mut W, buf: &mut Vec<u8>) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    writer.write_all(buf).await?;
+    buf.clear();
+    writer.flush().await
+}
+
+/// Write all of `bufs`, as few writes as possible if the writer supports vectored IO
+async fn write_all_vectored<W>(writer: &mut W, mut bufs: &mut [IoSlice<'_>]) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    // also drops leading empty slices
+    IoSlice::advance_slices(&mut bufs, 0);
+    while !bufs.is_empty() {
+        let n = writer.write_vectored(bufs).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        IoSlice::advance_slices(&mut bufs, n);
+    }
+
+    Ok(())
+}
+
+/// `attachment` disposition, see RFC 6266
+fn content_disposition(filename: &str) -> String {
+    // only the last path component, without characters which break the quoting
+    let filename = filename.rsplit(['/', '\\']).next().unwrap_or("");
+    let fallback: String = filename
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' && c != '%' => c,
+            _ => '_',
+        })
+        .collect();
+
+    match fallback == filename {
+        true => format!("attachment; filename=\"{filename}\""),
+        false => format!(
+            "attachment; filename=\"{fallback}\"; filename*=UTF-8''{}",
+            percent_encode(filename.as_bytes(), EncodeSet::Component)
+        ),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::task::Poll;
@@ -167,11 +486,12 @@ mod tests {
 
     #[test]
     async fn test_basic_response() {
-        let mut response = HttpResponse::new(200, "OK").with_body(HttpBody::from("Hello, World!"));
+        let mut response =
+            HttpResponse::new(StatusCode::OK).with_body(HttpBody::from("Hello, World!"));
         response.headers.insert("Content-Type", "text/plain");
 
         let mut buffer = Vec::new();
-        response.send(&mut buffer).await.unwrap();
+        response.send(&mut buffer, HttpMethod::Get).await.unwrap();
 
         let response_str = String::from_utf8_lossy(&buffer);
         assert!(response_str.contains("HTTP/1.1 200 OK"));
@@ -184,16 +504,363 @@ mod tests {
 
     #[test]
     async fn test_empty_request() {
-        let mut response = HttpResponse::new(204, "No Content");
+        let mut response = HttpResponse::new(StatusCode::NO_CONTENT);
 
         let mut buffer = Vec::new();
-        response.send(&mut buffer).await.unwrap();
+        response.send(&mut buffer, HttpMethod::Get).await.unwrap();
 
         let response_str = String::from_utf8_lossy(&buffer);
         assert!(response_str.contains("HTTP/1.1 204 No Content"));
         assert!(!response_str.contains("Content-Length"));
     }
 
+    #[test]
+    async fn test_status_and_reason() {
+        let response = HttpResponse::new(StatusCode::NOT_FOUND);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.reason(), "Not Found");
+
+        let mut response = HttpResponse::new(StatusCode::OK).with_reason("Fine\r\nX-Injected: 1");
+        assert_eq!(response.reason(), "FineX-Injected: 1");
+
+        let mut buffer = Vec::new();
+        response.send(&mut buffer, HttpMethod::Get).await.unwrap();
+        assert!(buffer.starts_with(b"HTTP/1.1 200 FineX-Injected: 1\r\n"));
+
+        // unregistered codes have an empty reason
+        response.set_status(StatusCode::from_u16(299).unwrap());
+        assert_eq!(response.reason(), "");
+    }
+
+    async fn to_string(mut response: HttpResponse) -> String {
+        let mut buffer = Vec::new();
+        response.send(&mut buffer, HttpMethod::Get).await.unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    async fn test_constructors() {
+        assert_eq!(
+            to_string(HttpResponse::text("hi")).await,
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nContent-Type: text/plain; charset=utf-8\r\n\r\nhi"
+        );
+
+        let html = HttpResponse::html("<p>hi</p>");
+        assert_eq!(
+            html.headers().get("content-type").unwrap(),
+            "text/html; charset=utf-8"
+        );
+
+        assert_eq!(
+            to_string(HttpResponse::redirect(StatusCode::SEE_OTHER, "/login")).await,
+            "HTTP/1.1 303 See Other\r\nContent-Length: 0\r\nLocation: /login\r\n\r\n"
+        );
+
+        // a location from the request can't add headers
+        let mut injected =
+            HttpResponse::redirect(StatusCode::FOUND, "/next\r\nSet-Cookie: session=stolen");
+        let mut buffer = Vec::new();
+        let error = injected
+            .send(&mut buffer, HttpMethod::Get)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(buffer.is_empty());
+
+        assert_eq!(HttpResponse::no_content().status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            HttpResponse::not_modified().status(),
+            StatusCode::NOT_MODIFIED
+        );
+    }
+
+    #[test]
+    async fn test_attachment() {
+        let response = HttpResponse::attachment("report.txt", HttpBody::from("data"));
+        assert_eq!(
+            response.headers().get("Content-Type").unwrap(),
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(
+            response.headers().get("Content-Disposition").unwrap(),
+            "attachment; filename=\"report.txt\""
+        );
+
+        assert_eq!(
+            content_disposition("../€ rates \"2024\".pdf"),
+            "attachment; filename=\"_ rates _2024_.pdf\"; \
+             filename*=UTF-8''%E2%82%AC%20rates%20%222024%22.pdf"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    async fn test_json() {
+        #[derive(serde::Serialize)]
+        struct User {
+            name: &'static str,
+            id: u32,
+        }
+
+        let response = HttpResponse::json(&User {
+            name: "Ferris",
+            id: 1,
+        })
+        .unwrap();
+        assert_eq!(
+            to_string(response).await,
+            "HTTP/1.1 200 OK\r\nContent-Length: 24\r\nContent-Type: application/json\r\n\r\n{\"name\":\"Ferris\",\"id\":1}"
+        );
+    }
+
+    async fn send_to(mut response: HttpResponse, method: HttpMethod) -> String {
+        let mut buffer = Vec::new();
+        response.send(&mut buffer, method).await.unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    async fn test_head_keeps_length_without_body() {
+        let response = HttpResponse::text("hello");
+        assert_eq!(
+            send_to(response, HttpMethod::Head).await,
+            "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n"
+        );
+
+        let response = HttpResponse::new(StatusCode::OK).with_streaming_body(&b"hello"[..], 16);
+        assert_eq!(
+            send_to(response, HttpMethod::Head).await,
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n"
+        );
+
+        // an explicit length for an empty HEAD body is kept
+        let response = HttpResponse::new(StatusCode::OK).insert_header("Content-Length", "42");
+        assert_eq!(
+            send_to(response, HttpMethod::Head).await,
+            "HTTP/1.1 200 OK\r\nContent-Length: 42\r\n\r\n"
+        );
+    }
+
+    #[test]
+    async fn test_no_content_has_no_body_or_framing() {
+        let response = HttpResponse::no_content()
+            .with_body(HttpBody::from("ignored"))
+            .insert_header("Content-Length", "7")
+            .insert_header("Transfer-Encoding", "chunked");
+        assert_eq!(
+            send_to(response, HttpMethod::Get).await,
+            "HTTP/1.1 204 No Content\r\n\r\n"
+        );
+
+        let response = HttpResponse::new(StatusCode::NO_CONTENT).with_streaming_body(&b"x"[..], 16);
+        assert_eq!(
+            send_to(response, HttpMethod::Get).await,
+            "HTTP/1.1 204 No Content\r\n\r\n"
+        );
+    }
+
+    #[test]
+    async fn test_informational_has_no_body_or_framing() {
+        let response = HttpResponse::new(StatusCode::EARLY_HINTS)
+            .with_body(HttpBody::from("ignored"))
+            .insert_header("Link", "</style.css>; rel=preload")
+            .insert_header("Content-Length", "7");
+        assert_eq!(
+            send_to(response, HttpMethod::Get).await,
+            "HTTP/1.1 103 Early Hints\r\nLink: </style.css>; rel=preload\r\n\r\n"
+        );
+    }
+
+    #[test]
+    async fn test_not_modified_has_no_body() {
+        // the body length is not sent, an explicit one is kept
+        let response = HttpResponse::not_modified()
+            .with_body(HttpBody::from("ignored"))
+            .insert_header("ETag", "\"v1\"");
+        assert_eq!(
+            send_to(response, HttpMethod::Get).await,
+            "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\n\r\n"
+        );
+
+        let response = HttpResponse::not_modified().insert_header("Content-Length", "42");
+        assert_eq!(
+            send_to(response, HttpMethod::Get).await,
+            "HTTP/1.1 304 Not Modified\r\nContent-Length: 42\r\n\r\n"
+        );
+    }
+
+    #[test]
+    async fn test_empty_body_has_zero_length() {
+        assert_eq!(
+            send_to(HttpResponse::new(StatusCode::OK), HttpMethod::Get).await,
+            "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n"
+        );
+    }
+
+    #[test]
+    async fn test_sized_streaming_body() {
+        let response =
+            HttpResponse::new(StatusCode::OK).with_sized_streaming_body(&b"hello world"[..], 11, 4);
+        assert_eq!(
+            send_to(response, HttpMethod::Get).await,
+            "HTTP/1.1 200 OK\r\nContent-Length: 11\r\n\r\nhello world"
+        );
+
+        // replaces the chunked encoding of a previous streaming body
+        let response = HttpResponse::new(StatusCode::OK)
+            .with_streaming_body(&b"abc"[..], 4)
+            .with_sized_streaming_body(&b"abc"[..], 3, 4);
+        assert_eq!(
+            send_to(response, HttpMethod::Get).await,
+            "HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\nabc"
+        );
+    }
+
+    #[test]
+    async fn test_sized_streaming_body_length_mismatch() {
+        let mut buffer = Vec::new();
+
+        let mut response =
+            HttpResponse::new(StatusCode::OK).with_sized_streaming_body(&b"short"[..], 10, 4);
+        let error = response
+            .send(&mut buffer, HttpMethod::Get)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        // the extra bytes are never written
+        buffer.clear();
+        let mut response =
+            HttpResponse::new(StatusCode::OK).with_sized_streaming_body(&b"too long"[..], 3, 4);
+        let error = response
+            .send(&mut buffer, HttpMethod::Get)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(buffer.is_empty());
+
+        // an explicit header is checked too
+        let mut response = HttpResponse::text("hello").insert_header("Content-Length", "4");
+        assert!(
+            response
+                .send(&mut Vec::new(), HttpMethod::Get)
+                .await
+                .is_err()
+        );
+    }
+
+    /// Count the writes which reach the writer
+    #[derive(Default)]
+    struct CountingWriter {
+        data: Vec<u8>,
+        writes: usize,
+    }
+
+    impl AsyncWrite for CountingWriter {
+        fn poll_write(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.writes += 1;
+            self.data.extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_write_vectored(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            bufs: &[IoSlice<'_>],
+        ) -> Poll<io::Result<usize>> {
+            self.writes += 1;
+            let mut n = 0;
+            for buf in bufs {
+                self.data.extend_from_slice(buf);
+                n += buf.len();
+            }
+            Poll::Ready(Ok(n))
+        }
+
+        fn is_write_vectored(&self) -> bool {
+            true
+        }
+
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    async fn test_coalesced_writes() {
+        let mut writer = CountingWriter::default();
+        let mut buf = Vec::new();
+
+        // head and body in a single write
+        let mut response = HttpResponse::text("hello").insert_header("X-A", "1");
+        response
+            .send_with_buffer(&mut writer, HttpMethod::Get, &mut buf)
+            .await
+            .unwrap();
+        assert_eq!(writer.writes, 1);
+        assert!(writer.data.ends_with(b"X-A: 1\r\n\r\nhello"));
+
+        // the buffer is reused for the next response
+        let capacity = buf.capacity();
+        let mut writer = CountingWriter::default();
+        let mut response = HttpResponse::no_content();
+        response
+            .send_with_buffer(&mut writer, HttpMethod::Get, &mut buf)
+            .await
+            .unwrap();
+        assert_eq!(writer.writes, 1);
+        assert_eq!(buf.capacity(), capacity);
+
+        // one write per chunk, the head goes with the first one
+        let mut writer = CountingWriter::default();
+        let mut response =
+            HttpResponse::new(StatusCode::OK).with_streaming_body(&b"abcdefgh"[..], 3);
+        response
+            .send_with_buffer(&mut writer, HttpMethod::Get, &mut buf)
+            .await
+            .unwrap();
+        assert_eq!(writer.writes, 4);
+        assert!(
+            writer
+                .data
+                .ends_with(b"\r\n\r\n3\r\nabc\r\n3\r\ndef\r\n2\r\ngh\r\n0\r\n\r\n")
+        );
+    }
+
+    #[test]
+    async fn test_body_piped_from_stream() {
+        // a request body can become a response body without copying
+        let source = HttpBody::from_reader(&b"piped"[..], 16);
+        let response = HttpResponse::new(StatusCode::OK).with_body(HttpBody::from_stream(source));
+        assert_eq!(
+            send_to(response, HttpMethod::Get).await,
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\npiped\r\n0\r\n\r\n"
+        );
+
+        // a sized body replaces the chunked encoding
+        let response = HttpResponse::new(StatusCode::OK)
+            .with_streaming_body(&b"x"[..], 16)
+            .with_body(HttpBody::from("sized"));
+        assert_eq!(
+            send_to(response, HttpMethod::Get).await,
+            "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nsized"
+        );
+    }
+
     #[test]
     async fn test_chunked_encoding() {
         struct TestReader {
@@ -232,11 +899,11 @@ mod tests {
             current: 0,
         };
 
-        let mut response = HttpResponse::new(200, "OK").with_streaming_body(reader, 1024);
+        let mut response = HttpResponse::new(StatusCode::OK).with_streaming_body(reader, 1024);
         response.headers.insert("Content-Type", "test/plain");
 
         let mut buffer = Vec::new();
-        response.send(&mut buffer).await.unwrap();
+        response.send(&mut buffer, HttpMethod::Get).await.unwrap();
 
         let response_str = String::from_utf8_lossy(&buffer);
         assert!(response_str.contains("Transfer-Encoding: chunked"));
@@ -251,4 +918,51 @@ mod tests {
 
         assert!(response_str.contains("0\r\n\r\n"));
     }
+
+    #[test]
+    async fn test_channel_body_flush_and_abort() {
+        use tokio::io::AsyncReadExt;
+
+        async fn read_some(client: &mut io::DuplexStream) -> String {
+            let mut buf = [0u8; 256];
+            let n = client.read(&mut buf).await.unwrap();
+            String::from_utf8_lossy(&buf[..n]).into_owned()
+        }
+
+        let (mut client, mut server) = io::duplex(1024);
+        let (sender, body) = HttpBody::channel();
+        let mut response = HttpResponse::new(StatusCode::OK).with_body(body);
+        let task = tokio::spawn(async move { response.send(&mut server, HttpMethod::Get).await });
+
+        // the head goes out on the first flush, before any data
+        sender.flush().await.unwrap();
+        assert_eq!(
+            read_some(&mut client).await,
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n"
+        );
+
+        sender.send("hello").await.unwrap();
+        sender.flush().await.unwrap();
+        assert_eq!(read_some(&mut client).await, "5\r\nhello\r\n");
+
+        // dropping the sender ends the body
+        drop(sender);
+        task.await.unwrap().unwrap();
+        assert_eq!(read_some(&mut client).await, "0\r\n\r\n");
+
+        // aborting fails the response without the last chunk
+        let (sender, body) = HttpBody::channel();
+        let mut response = HttpResponse::new(StatusCode::OK).with_body(body);
+        let task = tokio::spawn(async move {
+            let mut buffer = Vec::new();
+            let result = response.send(&mut buffer, HttpMethod::Get).await;
+            (result, buffer)
+        });
+
+        sender.send("partial").await.unwrap();
+        sender.abort();
+        let (result, buffer) = task.await.unwrap();
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::ConnectionAborted);
+        assert!(!String::from_utf8(buffer).unwrap().contains("0\r\n\r\n"));
+    }
 }
@@ -2,12 +2,20 @@ use std::{collections::HashMap, fmt::Debug, pin::Pin, sync::Arc};
 
 use tokio::sync::RwLock;
 
-use crate::{handler::HandlerFn, method::HttpMethod, request::HttpRequest, response::HttpResponse};
+use crate::{
+    body::HttpBody,
+    handler::{ErrorHandlerFn, HandlerFn},
+    method::HttpMethod,
+    request::HttpRequest,
+    response::HttpResponse,
+    status::StatusCode,
+};
 
 type ParamRoute = Option<(String, Box<RouteNode>)>;
 type StaticRoutes = HashMap<String, Arc<RouteNode>>;
 type Handlers = HashMap<HttpMethod, Arc<HandlerFn>>;
 type Middlewares = Vec<HandlerFn>;
+type ErrorHandlers = HashMap<StatusCode, ErrorHandlerFn>;
 
 /// Similar with Trie tree
 #[derive(Clone)]
@@ -59,14 +67,14 @@ impl Debug for RouteNode {
                 "handlers",
                 &format!(
                     "{{ {} handlers }}",
-                    &self.handlers.try_read().map(|h| h.len()).unwrap_or(0)
+                    self.handlers.try_read().map(|h| h.len()).unwrap_or(0)
                 ),
             )
             .field(
                 "static_routes",
                 &format!(
                     "{}",
-                    &self.static_routes.try_read().map(|r| r.len()).unwrap_or(0)
+                    self.static_routes.try_read().map(|r| r.len()).unwrap_or(0)
                 ),
             )
             .field(
@@ -107,6 +115,8 @@ pub struct HttpRouter {
     root: Arc<RouteNode>,
     /// Global middlewares
     global_middlewares: Vec<HandlerFn>,
+    /// Responses for errors the connection answers by itself
+    error_handlers: ErrorHandlers,
 }
 
 impl Default for HttpRouter {
@@ -114,6 +124,7 @@ impl Default for HttpRouter {
         HttpRouter {
             root: Arc::new(RouteNode::new()),
             global_middlewares: Vec::new(),
+            error_handlers: HashMap::new(),
         }
     }
 }
@@ -220,6 +231,29 @@ impl HttpRouter {
             .map(|handler| Arc::clone(&**handler)) // get fn and `&` it
     }
 
+    /// Build the response for `status` when the connection answers by itself
+    ///
+    /// Such as `404` for a path without a route or `400` for a malformed request.
+    pub fn error_handler<F>(mut self, status: StatusCode, handler: F) -> Self
+    where
+        F: Fn(StatusCode) -> HttpResponse + Send + Sync + 'static,
+    {
+        self.error_handlers.insert(status, Arc::new(handler));
+        self
+    }
+
+    /// The response for an error, from the error handler or a plain text default
+    pub fn error_response(&self, status: StatusCode) -> HttpResponse {
+        if let Some(handler) = self.error_handlers.get(&status) {
+            return handler(status);
+        }
+
+        let reason = status.canonical_reason().unwrap_or("Error");
+        HttpResponse::new(status)
+            .insert_header("Content-Type", "text/plain")
+            .with_body(HttpBody::from(reason))
+    }
+
     /// Add a global middleware
     pub fn add_global_middleware(&mut self, handler: HandlerFn) -> &mut Self {
         self.global_middlewares.push(handler);
@@ -235,6 +269,10 @@ impl Debug for HttpRouter {
                 "global_middlewares",
                 &format!("{{ {} middleware(s) }}", self.global_middlewares.len()),
             )
+            .field(
+                "error_handlers",
+                &format!("{{ {} error handler(s) }}", self.error_handlers.len()),
+            )
             .finish()
     }
 }
@@ -242,7 +280,7 @@ impl Debug for HttpRouter {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{request::HttpRequest, response::HttpResponse};
+    use crate::{request::HttpRequest, response::HttpResponse, status::StatusCode};
     use tokio::test;
 
     #[test]
@@ -253,15 +291,17 @@ mod tests {
                 "/",
                 Arc::new(|_req| {
                     Box::pin(async {
-                        HttpResponse::new(200, "OK").with_body(crate::body::HttpBody::InMemory {
-                            data: b"Hello world".to_vec(),
-                        })
+                        HttpResponse::new(StatusCode::OK).with_body(
+                            crate::body::HttpBody::InMemory {
+                                data: b"Hello world".to_vec().into(),
+                            },
+                        )
                     })
                 }),
             )
             .await;
 
-        println!("{:#?}", &router);
+        println!("{:#?}", router);
 
         let root_handlers = router.root.handlers.read().await;
         assert!(root_handlers.contains_key(&HttpMethod::Get));
@@ -279,4 +319,24 @@ mod tests {
     async fn test_wildcard_routing() {
         
     }
+
+    #[test]
+    async fn test_error_response() {
+        let router = HttpRouter::new().error_handler(StatusCode::NOT_FOUND, |status| {
+            HttpResponse::new(status).with_body(HttpBody::from("custom"))
+        });
+
+        let response = router.error_response(StatusCode::NOT_FOUND);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.body().content_length(), Some(6));
+
+        // without a handler, the reason phrase as plain text
+        let response = router.error_response(StatusCode::BAD_REQUEST);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.headers().get("Content-Type").unwrap(),
+            "text/plain"
+        );
+        assert_eq!(response.body().content_length(), Some(11));
+    }
 }
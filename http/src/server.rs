@@ -1,36 +1,185 @@
-use std::sync::Arc;
+use std::{
+    fmt,
+    future::{Future, pending, poll_fn},
+    io,
+    net::SocketAddr,
+    path::PathBuf,
+    pin::pin,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    task::{Context, Poll, ready},
+};
 
 use tokio::{
-    net::TcpListener,
-    spawn,
-    sync::Semaphore,
+    net::{TcpListener, TcpSocket, lookup_host},
+    select, spawn,
+    sync::{Semaphore, oneshot, watch},
+    task::{JoinHandle, JoinSet},
     time::{self, Duration},
 };
 
-use crate::{connect::HttpConnection, error::ServerError, router::HttpRouter};
+#[cfg(unix)]
+use std::{
+    os::unix::fs::{FileTypeExt, PermissionsExt},
+    path::Path,
+};
+#[cfg(unix)]
+use tokio::{
+    fs,
+    net::{UnixListener, UnixStream},
+};
+
+use crate::{
+    connect::{BoxedTransport, ConnectionInfo, HttpConnection},
+    error::ServerError,
+    forwarded::TrustedProxies,
+    router::HttpRouter,
+};
 
 const MAX_CONNECTIONS: usize = 1000;
-const CONNECTION_TIMEOUT: usize = 5;
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+/// Listen backlog, the same as `TcpListener::bind`
+const DEFAULT_BACKLOG: u32 = 1024;
+/// Wait before accepting again when no permit could be acquired
+const PERMIT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Timeouts of a connection
+///
+/// Waiting for a request ends with a silent close, a request head which is
+/// not complete in time is answered with `408 Request Timeout`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    /// From accepting the connection to the first byte of the first request
+    pub first_byte: Duration,
+    /// From the first byte of a request to the end of its head
+    pub headers: Duration,
+    /// Longest wait for more of the request body, the read fails with `TimedOut`
+    pub body: Duration,
+    /// Between the end of a response and the next request on the connection
+    pub idle: Duration,
+    /// Longest time a response write may make no progress
+    pub write: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            first_byte: Duration::from_secs(5),
+            headers: Duration::from_secs(10),
+            body: Duration::from_secs(10),
+            idle: Duration::from_secs(5),
+            write: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Options of a TCP listening socket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketOptions {
+    /// `TCP_NODELAY` on accepted connections, small writes are not delayed
+    pub nodelay: bool,
+    /// Connections the kernel queues until they are accepted
+    pub backlog: u32,
+    /// `SO_REUSEADDR`, bind again while old connections are in `TIME_WAIT`
+    pub reuse_address: bool,
+    /// `SO_REUSEPORT`, several sockets share the port, ignored outside of Unix
+    pub reuse_port: bool,
+    /// `SO_KEEPALIVE`, probe idle connections, inherited by accepted connections
+    pub keepalive: bool,
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        SocketOptions {
+            nodelay: false,
+            backlog: DEFAULT_BACKLOG,
+            reuse_address: true,
+            reuse_port: false,
+            keepalive: false,
+        }
+    }
+}
+
+/// An address to listen on
+///
+/// IPv4 and IPv6 on the same port need two listeners only if the system
+/// binds `[::]` to IPv6 alone, otherwise `[::]` accepts both.
+#[derive(Debug, Clone)]
+pub struct ListenerConfig {
+    /// `host:port` or `unix:/path/to.sock`, port 0 picks a free port
+    pub address: String,
+    /// Serves this listener instead of `ServerConfig::router`
+    pub router: Option<Arc<HttpRouter>>,
+    /// Socket options, TCP only
+    pub options: SocketOptions,
+    /// Permissions of the Unix socket file, such as `0o660`, `None` keeps the umask default
+    pub unix_socket_mode: Option<u32>,
+    /// Require a PROXY protocol header on every connection, for a load balancer in front
+    pub proxy_protocol: bool,
+}
+
+impl ListenerConfig {
+    pub fn new(address: impl Into<String>) -> Self {
+        ListenerConfig {
+            address: address.into(),
+            router: None,
+            options: SocketOptions::default(),
+            unix_socket_mode: None,
+            proxy_protocol: false,
+        }
+    }
+
+    pub fn router(mut self, router: HttpRouter) -> Self {
+        self.router = Some(Arc::new(router));
+        self
+    }
+
+    pub fn options(mut self, options: SocketOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn unix_socket_mode(mut self, mode: u32) -> Self {
+        self.unix_socket_mode = Some(mode);
+        self
+    }
+
+    pub fn proxy_protocol(mut self, required: bool) -> Self {
+        self.proxy_protocol = required;
+        self
+    }
+}
 
 #[derive(Clone)]
 pub struct ServerConfig {
-    /// Server address
-    pub address: String,
-    /// Router
+    /// Addresses to listen on
+    pub listeners: Vec<ListenerConfig>,
+    /// Router of listeners without their own
     pub router: Arc<HttpRouter>,
-    /// Timeout for each connection
-    pub timeout: usize,
+    /// Timeouts of each connection
+    pub timeouts: Timeouts,
+    /// Maximum number of requests per connection, `None` for no limit
+    pub max_requests: Option<usize>,
     /// Maximum number of connections
     pub max_connections: usize,
+    /// How long active connections get to finish on shutdown
+    pub shutdown_timeout: Duration,
+    /// Proxies allowed to name the client in forwarding headers, `None` ignores the headers
+    pub trusted_proxies: Option<Arc<TrustedProxies>>,
 }
 
 impl Default for ServerConfig {
     fn default() -> Self {
         Self {
-            address: "127.0.0.1:8080".to_string(),
+            listeners: vec![ListenerConfig::new("127.0.0.1:8080")],
             router: Arc::new(HttpRouter::new()),
-            timeout: CONNECTION_TIMEOUT,
+            timeouts: Timeouts::default(),
+            max_requests: None,
             max_connections: MAX_CONNECTIONS,
+            shutdown_timeout: SHUTDOWN_TIMEOUT,
+            trusted_proxies: None,
         }
     }
 }
@@ -40,17 +189,20 @@ impl Default for ServerConfig {
 pub struct HttpServer {
     /// Server configuration
     pub config: ServerConfig,
+    /// Panics of handlers and response bodies
+    panics: Arc<AtomicUsize>,
 }
 
 impl HttpServer {
     pub fn new() -> Self {
-        HttpServer {
-            config: ServerConfig::default(),
-        }
+        HttpServer::with_config(ServerConfig::default())
     }
 
     pub fn with_config(config: ServerConfig) -> Self {
-        HttpServer { config }
+        HttpServer {
+            config,
+            panics: Arc::default(),
+        }
     }
 
     pub fn set_config(&mut self, config: ServerConfig) -> &mut Self {
@@ -63,51 +215,801 @@ impl HttpServer {
         self
     }
 
+    /// Listen on `port` alone, replacing the configured listeners
     pub fn set_port(&mut self, port: &str) -> &mut Self {
-        self.config.address = port.to_string();
+        self.config.listeners = vec![ListenerConfig::new(port)];
         self
     }
 
+    /// Listen on one more address
+    pub fn add_listener(&mut self, listener: ListenerConfig) -> &mut Self {
+        self.config.listeners.push(listener);
+        self
+    }
+
+    /// Number of handlers and response bodies which panicked
+    ///
+    /// A panic is answered with `500` if no response was started, the
+    /// connection is closed either way.
+    pub fn panic_count(&self) -> usize {
+        self.panics.load(Ordering::Relaxed)
+    }
+
+    /// Serve until the process is killed
     pub async fn run(&self) -> Result<(), ServerError> {
-        let listener = TcpListener::bind(&self.config.address).await?;
+        self.run_with_shutdown(pending()).await.map(|_| ())
+    }
 
-        println!("Server running http://{}", self.config.address);
+    /// Serve until `signal` completes, then shut down gracefully
+    ///
+    /// No new connections are accepted, idle connections are closed and
+    /// responses in progress are sent with `Connection: close`. Connections
+    /// still active after `shutdown_timeout` are aborted.
+    pub async fn run_with_shutdown(
+        &self,
+        signal: impl Future<Output = ()>,
+    ) -> Result<ShutdownReport, ServerError> {
+        let listeners = Listener::bind_all(&self.config).await?;
 
-        let semaphore = Arc::new(Semaphore::new(self.config.max_connections));
+        serve(listeners, self.config.clone(), self.panics.clone(), signal).await
+    }
 
-        loop {
-            let permit = match semaphore.clone().acquire_owned().await {
-                Ok(permit) => permit,
-                Err(e) => {
-                    eprintln!("Get permit failed: {e}");
-                    time::sleep(Duration::from_secs(self.config.timeout as u64)).await;
-                    continue;
-                }
-            };
+    /// Bind the addresses and serve in a background task
+    pub async fn spawn(&self) -> Result<ServerHandle, ServerError> {
+        let listeners = Listener::bind_all(&self.config).await?;
+        let local_addrs = listeners
+            .iter()
+            .map(|listener| listener.local_addr.clone())
+            .collect();
 
-            let (socket, addr) = match listener.accept().await {
-                Ok(connection) => connection,
+        let (shutdown, signal) = oneshot::channel();
+        let signal = async {
+            // a dropped handle leaves the server running
+            if signal.await.is_err() {
+                pending::<()>().await;
+            }
+        };
+        let task = spawn(serve(
+            listeners,
+            self.config.clone(),
+            self.panics.clone(),
+            signal,
+        ));
+
+        Ok(ServerHandle {
+            local_addrs,
+            panics: self.panics.clone(),
+            shutdown,
+            task,
+        })
+    }
+}
+
+/// Address a server is bound to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl ListenAddr {
+    /// The socket address of a TCP listener
+    pub fn as_tcp(&self) -> Option<SocketAddr> {
+        match self {
+            ListenAddr::Tcp(addr) => Some(*addr),
+            ListenAddr::Unix(_) => None,
+        }
+    }
+}
+
+/// Printed the way `ListenerConfig::address` is written
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddr::Tcp(addr) => write!(f, "{addr}"),
+            ListenAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// A bound listener and the router serving it
+struct Listener {
+    socket: ListenSocket,
+    local_addr: ListenAddr,
+    router: Arc<HttpRouter>,
+    proxy_protocol: bool,
+}
+
+enum ListenSocket {
+    Tcp {
+        listener: TcpListener,
+        nodelay: bool,
+    },
+    #[cfg(unix)]
+    Unix(UnixListener, PathBuf),
+}
+
+impl Listener {
+    /// Bind every listener of `config`, none if one fails
+    async fn bind_all(config: &ServerConfig) -> Result<Vec<Self>, ServerError> {
+        if config.listeners.is_empty() {
+            return Err(ServerError::ConfigError(
+                "No listeners configured".to_string(),
+            ));
+        }
+
+        let mut listeners = Vec::with_capacity(config.listeners.len());
+        for listener in &config.listeners {
+            match Listener::bind(listener, &config.router).await {
+                Ok(listener) => {
+                    println!("Server running on {}", listener.local_addr);
+                    listeners.push(listener);
+                }
                 Err(e) => {
-                    eprintln!("Accept connection failed: {e}",);
-                    continue;
+                    for listener in listeners {
+                        listener.close().await;
+                    }
+                    return Err(e);
                 }
-            };
+            }
+        }
 
-            println!("New connection from {addr}",);
+        Ok(listeners)
+    }
 
-            let mut connection = HttpConnection::new(
-                socket,
-                (*self.config.router).clone(),
-                self.config.timeout as u64,
-            );
+    async fn bind(config: &ListenerConfig, router: &Arc<HttpRouter>) -> Result<Self, ServerError> {
+        let socket = match config.address.strip_prefix("unix:") {
+            #[cfg(unix)]
+            Some(path) => {
+                let path = PathBuf::from(path);
+                let listener = bind_unix(&path, config.unix_socket_mode).await?;
+                ListenSocket::Unix(listener, path)
+            }
+            #[cfg(not(unix))]
+            Some(_) => {
+                return Err(ServerError::ConfigError(
+                    "Unix sockets are not supported on this platform".to_string(),
+                ));
+            }
+            None => ListenSocket::Tcp {
+                listener: bind_tcp(&config.address, &config.options).await?,
+                nodelay: config.options.nodelay,
+            },
+        };
+        let local_addr = match &socket {
+            ListenSocket::Tcp { listener, .. } => ListenAddr::Tcp(listener.local_addr()?),
+            #[cfg(unix)]
+            ListenSocket::Unix(_, path) => ListenAddr::Unix(path.clone()),
+        };
 
-            spawn(async move {
-                let _permit = permit;
+        Ok(Listener {
+            socket,
+            local_addr,
+            router: config.router.clone().unwrap_or_else(|| router.clone()),
+            proxy_protocol: config.proxy_protocol,
+        })
+    }
 
-                if let Err(e) = connection.process().await {
-                    eprintln!("Connection error from {addr}: {e:?}");
+    fn poll_accept(
+        &self,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<(BoxedTransport, ConnectionInfo)>> {
+        match &self.socket {
+            ListenSocket::Tcp { listener, nodelay } => {
+                let (socket, addr) = ready!(listener.poll_accept(cx))?;
+                socket.set_nodelay(*nodelay)?;
+                let info = ConnectionInfo {
+                    peer_addr: Some(addr),
+                    local_addr: socket.local_addr().ok(),
+                    ..ConnectionInfo::default()
                 };
-            });
+                Poll::Ready(Ok((Box::new(socket), info)))
+            }
+            #[cfg(unix)]
+            ListenSocket::Unix(listener, _) => {
+                let (socket, _) = ready!(listener.poll_accept(cx))?;
+                let info = ConnectionInfo {
+                    peer_cred: socket
+                        .peer_cred()
+                        .ok()
+                        .map(|cred| crate::connect::PeerCred {
+                            uid: cred.uid(),
+                            gid: cred.gid(),
+                            pid: cred.pid(),
+                        }),
+                    ..ConnectionInfo::default()
+                };
+                Poll::Ready(Ok((Box::new(socket), info)))
+            }
+        }
+    }
+
+    /// Remove the socket file of a Unix listener
+    async fn close(self) {
+        #[cfg(unix)]
+        if let ListenSocket::Unix(listener, path) = self.socket {
+            drop(listener);
+            if let Err(e) = fs::remove_file(&path).await {
+                eprintln!("Remove socket {} failed: {e}", path.display());
+            }
+        }
+    }
+}
+
+/// Accept from whichever listener is ready first, starting after the last one
+async fn accept(
+    listeners: &[Listener],
+    next: &mut usize,
+) -> (usize, io::Result<(BoxedTransport, ConnectionInfo)>) {
+    poll_fn(|cx| {
+        for offset in 0..listeners.len() {
+            let index = (*next + offset) % listeners.len();
+            if let Poll::Ready(accepted) = listeners[index].poll_accept(cx) {
+                *next = index + 1;
+                return Poll::Ready((index, accepted));
+            }
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+/// Bind the first address `address` resolves to which can be bound
+async fn bind_tcp(address: &str, options: &SocketOptions) -> io::Result<TcpListener> {
+    let mut last_error = None;
+    for addr in lookup_host(address).await? {
+        match bind_tcp_addr(addr, options) {
+            Ok(listener) => return Ok(listener),
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{address} did not resolve to any address"),
+        )
+    }))
+}
+
+fn bind_tcp_addr(addr: SocketAddr, options: &SocketOptions) -> io::Result<TcpListener> {
+    let socket = if addr.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    socket.set_reuseaddr(options.reuse_address)?;
+    #[cfg(unix)]
+    socket.set_reuseport(options.reuse_port)?;
+    socket.set_keepalive(options.keepalive)?;
+    socket.bind(addr)?;
+    socket.listen(options.backlog)
+}
+
+/// Bind a Unix socket, replacing a stale socket file left by an earlier run
+#[cfg(unix)]
+async fn bind_unix(path: &Path, mode: Option<u32>) -> Result<UnixListener, ServerError> {
+    if let Ok(metadata) = fs::symlink_metadata(path).await {
+        if !metadata.file_type().is_socket() {
+            return Err(ServerError::ConfigError(format!(
+                "{} exists and is not a socket",
+                path.display()
+            )));
+        }
+        // nobody listening means the file is stale
+        match UnixStream::connect(path).await {
+            Ok(_) => {
+                return Err(ServerError::ConfigError(format!(
+                    "{} is in use by another server",
+                    path.display()
+                )));
+            }
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                fs::remove_file(path).await?;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    match mode {
+        Some(mode) => bind_unix_with_mode(path, mode).await,
+        None => Ok(UnixListener::bind(path)?),
+    }
+}
+
+/// Bind in a private directory and move the socket into place once its mode is set
+///
+/// Binding at `path` directly would leave the socket open to anyone the
+/// umask allows until the mode is changed.
+#[cfg(unix)]
+async fn bind_unix_with_mode(path: &Path, mode: u32) -> Result<UnixListener, ServerError> {
+    let parent = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let name = path.file_name().ok_or_else(|| {
+        ServerError::ConfigError(format!("{} is not a socket path", path.display()))
+    })?;
+    let mut private_name = std::ffi::OsString::from(".");
+    private_name.push(name);
+    private_name.push(format!(".{}", std::process::id()));
+    let private = parent.join(private_name);
+
+    fs::DirBuilder::new().mode(0o700).create(&private).await?;
+    let socket = private.join(name);
+    let bound = async {
+        let listener = UnixListener::bind(&socket)?;
+        fs::set_permissions(&socket, std::fs::Permissions::from_mode(mode)).await?;
+        fs::rename(&socket, path).await?;
+        Ok::<_, io::Error>(listener)
+    }
+    .await;
+
+    if bound.is_err() {
+        let _ = fs::remove_file(&socket).await;
+    }
+    let _ = fs::remove_dir(&private).await;
+
+    Ok(bound?)
+}
+
+/// Connections left when a server shut down
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// Connections which finished before the deadline
+    pub drained: usize,
+    /// Connections aborted at the deadline
+    pub aborted: usize,
+}
+
+/// A server running in the background, see `HttpServer::spawn`
+#[must_use]
+pub struct ServerHandle {
+    local_addrs: Vec<ListenAddr>,
+    panics: Arc<AtomicUsize>,
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<Result<ShutdownReport, ServerError>>,
+}
+
+impl ServerHandle {
+    /// The address of the first listener
+    pub fn local_addr(&self) -> &ListenAddr {
+        &self.local_addrs[0]
+    }
+
+    /// The addresses of all listeners, in the order they were configured
+    pub fn local_addrs(&self) -> &[ListenAddr] {
+        &self.local_addrs
+    }
+
+    /// Number of handlers and response bodies which panicked
+    pub fn panic_count(&self) -> usize {
+        self.panics.load(Ordering::Relaxed)
+    }
+
+    /// Shut down gracefully and wait until it is done
+    pub async fn shutdown(self) -> Result<ShutdownReport, ServerError> {
+        let _ = self.shutdown.send(());
+        self.task
+            .await
+            .map_err(|e| ServerError::InternalError(e.to_string()))?
+    }
+}
+
+/// Accept connections until `signal` completes, then drain them
+async fn serve(
+    listeners: Vec<Listener>,
+    config: ServerConfig,
+    panics: Arc<AtomicUsize>,
+    signal: impl Future<Output = ()>,
+) -> Result<ShutdownReport, ServerError> {
+    let mut signal = pin!(signal);
+    let (shutdown, shutdown_rx) = watch::channel(false);
+    let semaphore = Arc::new(Semaphore::new(config.max_connections));
+    let mut connections = JoinSet::new();
+    let mut next = 0;
+
+    loop {
+        // forget the connections which are done
+        while connections.try_join_next().is_some() {}
+
+        let permit = select! {
+            permit = semaphore.clone().acquire_owned() => permit,
+            _ = &mut signal => break,
+        };
+        let permit = match permit {
+            Ok(permit) => permit,
+            Err(e) => {
+                eprintln!("Get permit failed: {e}");
+                time::sleep(PERMIT_RETRY_DELAY).await;
+                continue;
+            }
+        };
+
+        let (index, accepted) = select! {
+            accepted = accept(&listeners, &mut next) => accepted,
+            _ = &mut signal => break,
+        };
+        let (socket, info) = match accepted {
+            Ok(connection) => connection,
+            Err(e) => {
+                eprintln!("Accept connection failed: {e}",);
+                continue;
+            }
+        };
+
+        println!("New connection from {info}",);
+
+        let peer = info.to_string();
+        let mut connection = HttpConnection::from_transport(
+            socket,
+            (*listeners[index].router).clone(),
+            config.timeouts,
+        );
+        connection.set_info(info);
+        connection.max_requests(config.max_requests);
+        connection.trusted_proxies(config.trusted_proxies.clone());
+        connection.proxy_protocol(listeners[index].proxy_protocol);
+        connection.panic_counter(panics.clone());
+        connection.shutdown_signal(shutdown_rx.clone());
+
+        connections.spawn(async move {
+            let _permit = permit;
+
+            if let Err(e) = connection.process().await {
+                eprintln!("Connection error from {peer}: {e:?}");
+            };
+        });
+    }
+
+    // stop accepting, then let the active connections finish
+    for listener in listeners {
+        listener.close().await;
+    }
+    let _ = shutdown.send(true);
+
+    let active = connections.len();
+    println!("Server shutting down, {active} active connections");
+
+    let _ = time::timeout(config.shutdown_timeout, async {
+        while connections.join_next().await.is_some() {}
+    })
+    .await;
+
+    let aborted = connections.len();
+    connections.shutdown().await;
+    if aborted > 0 {
+        eprintln!("Aborted {aborted} connections after the shutdown timeout");
+    }
+
+    Ok(ShutdownReport {
+        drained: active - aborted,
+        aborted,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{request::HttpRequest, response::HttpResponse};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+        test,
+    };
+
+    async fn fast(_req: HttpRequest) -> HttpResponse {
+        HttpResponse::text("fast")
+    }
+
+    async fn slow(_req: HttpRequest) -> HttpResponse {
+        time::sleep(Duration::from_millis(300)).await;
+        HttpResponse::text("slow")
+    }
+
+    async fn never(_req: HttpRequest) -> HttpResponse {
+        pending().await
+    }
+
+    async fn panics(_req: HttpRequest) -> HttpResponse {
+        panic!("handler bug")
+    }
+
+    async fn spawn_server(shutdown_timeout: Duration) -> ServerHandle {
+        let router = HttpRouter::new()
+            .get("/fast", fast)
+            .await
+            .get("/slow", slow)
+            .await
+            .get("/never", never)
+            .await
+            .get("/panic", panics)
+            .await;
+
+        HttpServer::with_config(ServerConfig {
+            listeners: vec![ListenerConfig::new("127.0.0.1:0")],
+            router: Arc::new(router),
+            shutdown_timeout,
+            ..ServerConfig::default()
+        })
+        .spawn()
+        .await
+        .unwrap()
+    }
+
+    async fn request(addr: SocketAddr, path: &str) -> TcpStream {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        stream.write_all(request.as_bytes()).await.unwrap();
+        stream
+    }
+
+    #[test]
+    async fn test_graceful_shutdown() {
+        let server = spawn_server(SHUTDOWN_TIMEOUT).await;
+        let addr = server.local_addr().as_tcp().unwrap();
+
+        // an idle keep-alive connection
+        let mut idle = request(addr, "/fast").await;
+        let mut buf = [0u8; 1024];
+        let n = idle.read(&mut buf).await.unwrap();
+        let response = String::from_utf8_lossy(&buf[..n]);
+        assert!(response.contains("Connection: keep-alive\r\n"));
+        assert!(response.ends_with("fast"));
+
+        // a request in progress when the shutdown starts
+        let mut active = request(addr, "/slow").await;
+        time::sleep(Duration::from_millis(50)).await;
+
+        let report = server.shutdown().await.unwrap();
+        assert_eq!(
+            report,
+            ShutdownReport {
+                drained: 2,
+                aborted: 0
+            }
+        );
+
+        // the idle connection was closed, the active one finished
+        assert_eq!(idle.read(&mut buf).await.unwrap(), 0);
+        let mut response = String::new();
+        active.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Connection: close\r\n"));
+        assert!(response.ends_with("slow"));
+
+        // no new connections
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+
+    #[test]
+    async fn test_shutdown_deadline() {
+        let server = spawn_server(Duration::ZERO).await;
+        let mut stuck = request(server.local_addr().as_tcp().unwrap(), "/never").await;
+        time::sleep(Duration::from_millis(50)).await;
+
+        let report = server.shutdown().await.unwrap();
+        assert_eq!(
+            report,
+            ShutdownReport {
+                drained: 0,
+                aborted: 1
+            }
+        );
+
+        let mut buf = Vec::new();
+        assert_eq!(stuck.read_to_end(&mut buf).await.unwrap(), 0);
+    }
+
+    #[test]
+    async fn test_multiple_listeners() {
+        async fn admin(_req: HttpRequest) -> HttpResponse {
+            HttpResponse::text("admin")
+        }
+
+        let admin_router = HttpRouter::new().get("/admin", admin).await;
+        let options = SocketOptions {
+            nodelay: true,
+            backlog: 16,
+            keepalive: true,
+            ..SocketOptions::default()
+        };
+        let mut server = HttpServer::with_config(ServerConfig {
+            router: Arc::new(HttpRouter::new().get("/fast", fast).await),
+            listeners: vec![ListenerConfig::new("127.0.0.1:0").options(options)],
+            ..ServerConfig::default()
+        });
+        server.add_listener(ListenerConfig::new("localhost:0").router(admin_router));
+        let server = server.spawn().await.unwrap();
+
+        let addrs: Vec<_> = server
+            .local_addrs()
+            .iter()
+            .map(|addr| addr.as_tcp().unwrap())
+            .collect();
+        assert_eq!(addrs.len(), 2);
+        assert_eq!(server.local_addr().as_tcp(), Some(addrs[0]));
+        assert_ne!(addrs[0].port(), 0);
+        assert_ne!(addrs[0].port(), addrs[1].port());
+
+        // every listener is served by its own router
+        for (addr, path, expected) in [
+            (addrs[0], "/fast", "HTTP/1.1 200 OK\r\n"),
+            (addrs[0], "/admin", "HTTP/1.1 404 Not Found\r\n"),
+            (addrs[1], "/admin", "HTTP/1.1 200 OK\r\n"),
+            (addrs[1], "/fast", "HTTP/1.1 404 Not Found\r\n"),
+        ] {
+            let mut stream = request(addr, path).await;
+            let mut buf = [0u8; 1024];
+            let n = stream.read(&mut buf).await.unwrap();
+            assert!(String::from_utf8_lossy(&buf[..n]).starts_with(expected));
+        }
+
+        server.shutdown().await.unwrap();
+    }
+
+    #[test]
+    async fn test_listener_bind_failure() {
+        let taken = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ServerConfig {
+            listeners: vec![
+                ListenerConfig::new("127.0.0.1:0"),
+                ListenerConfig::new(taken.local_addr().unwrap().to_string()).options(
+                    SocketOptions {
+                        reuse_address: false,
+                        ..SocketOptions::default()
+                    },
+                ),
+            ],
+            ..ServerConfig::default()
+        };
+        assert!(HttpServer::with_config(config).spawn().await.is_err());
+
+        let config = ServerConfig {
+            listeners: Vec::new(),
+            ..ServerConfig::default()
+        };
+        assert!(matches!(
+            HttpServer::with_config(config).spawn().await,
+            Err(ServerError::ConfigError(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    async fn test_reuse_port() {
+        let options = SocketOptions {
+            reuse_port: true,
+            ..SocketOptions::default()
+        };
+        let first = HttpServer::with_config(ServerConfig {
+            listeners: vec![ListenerConfig::new("127.0.0.1:0").options(options)],
+            ..ServerConfig::default()
+        })
+        .spawn()
+        .await
+        .unwrap();
+
+        // a second server shares the port
+        let addr = first.local_addr().to_string();
+        let second = HttpServer::with_config(ServerConfig {
+            listeners: vec![ListenerConfig::new(addr).options(options)],
+            ..ServerConfig::default()
+        })
+        .spawn()
+        .await
+        .unwrap();
+        assert_eq!(first.local_addr(), second.local_addr());
+
+        first.shutdown().await.unwrap();
+        second.shutdown().await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    async fn test_unix_socket() {
+        use std::os::unix::fs::MetadataExt;
+
+        async fn whoami(req: HttpRequest) -> HttpResponse {
+            let cred = req.connection.peer_cred.unwrap();
+            HttpResponse::text(format!("{} {}", cred.uid, cred.pid.unwrap()))
         }
+
+        let path = std::env::temp_dir().join(format!("http-test-{}.sock", std::process::id()));
+        // a socket file left over by a server which is gone
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+
+        let router = HttpRouter::new().get("/whoami", whoami).await;
+        let server = HttpServer::with_config(ServerConfig {
+            listeners: vec![
+                ListenerConfig::new(format!("unix:{}", path.display())).unix_socket_mode(0o600),
+            ],
+            router: Arc::new(router),
+            ..ServerConfig::default()
+        })
+        .spawn()
+        .await
+        .unwrap();
+        assert_eq!(server.local_addr(), &ListenAddr::Unix(path.clone()));
+        assert_eq!(
+            server.local_addr().to_string(),
+            format!("unix:{}", path.display())
+        );
+
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(metadata.mode() & 0o777, 0o600);
+        // the private directory it was bound in is gone
+        let name = path.file_name().unwrap().to_str().unwrap();
+        let leftover = std::fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .any(|entry| {
+                entry
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(&format!(".{name}"))
+            });
+        assert!(!leftover);
+
+        // the socket is in use now
+        let config = ServerConfig {
+            listeners: vec![ListenerConfig::new(format!("unix:{}", path.display()))],
+            ..ServerConfig::default()
+        };
+        assert!(matches!(
+            HttpServer::with_config(config).spawn().await,
+            Err(ServerError::ConfigError(_))
+        ));
+
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+        stream
+            .write_all(b"GET /whoami HTTP/1.1\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let expected = format!("{} {}", metadata.uid(), std::process::id());
+        assert!(response.ends_with(&expected));
+
+        server.shutdown().await.unwrap();
+        assert!(!path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    async fn test_unix_socket_keeps_other_files() {
+        let path = std::env::temp_dir().join(format!("http-test-{}.file", std::process::id()));
+        std::fs::write(&path, "data").unwrap();
+
+        let config = ServerConfig {
+            listeners: vec![ListenerConfig::new(format!("unix:{}", path.display()))],
+            ..ServerConfig::default()
+        };
+        assert!(matches!(
+            HttpServer::with_config(config).spawn().await,
+            Err(ServerError::ConfigError(_))
+        ));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "data");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    async fn test_handler_panic_is_500() {
+        let server = spawn_server(SHUTDOWN_TIMEOUT).await;
+
+        let mut stream = request(server.local_addr().as_tcp().unwrap(), "/panic").await;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+        assert!(response.contains("Connection: close\r\n"));
+        assert_eq!(server.panic_count(), 1);
+
+        // the server is still serving
+        let mut stream = request(server.local_addr().as_tcp().unwrap(), "/fast").await;
+        let mut buf = [0u8; 1024];
+        let n = stream.read(&mut buf).await.unwrap();
+        assert!(String::from_utf8_lossy(&buf[..n]).ends_with("fast"));
+
+        server.shutdown().await.unwrap();
     }
 }
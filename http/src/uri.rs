@@ -1,31 +1,324 @@
-#[derive(Debug, Default)]
+use crate::error::ServerError;
+
+/// Form of the request target, see RFC 9112 section 3.2
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UriForm {
+    /// `/path?query`, used by most requests
+    #[default]
+    Origin,
+    /// `http://host/path?query`, used when talking to a proxy
+    Absolute,
+    /// `host:port`, only used by `CONNECT`
+    Authority,
+    /// `*`, only used by `OPTIONS`
+    Asterisk,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct HttpUri {
-    /// HTTP URI path
+    /// HTTP URI path, still percent-encoded
     pub path: String,
+    /// Query string without the leading `?`
+    pub query: Option<String>,
+    /// Scheme, only in absolute-form
+    pub scheme: Option<String>,
+    /// Host and optional port, in absolute-form and authority-form
+    pub authority: Option<String>,
+    /// Form of the request target
+    pub form: UriForm,
+    /// Request target as received
+    raw: String,
 }
 
 impl HttpUri {
     pub fn new() -> Self {
         HttpUri {
             path: String::new(),
+            query: None,
+            scheme: None,
+            authority: None,
+            form: UriForm::Origin,
+            raw: String::new(),
+        }
+    }
+
+    /// Parse a request target
+    pub fn parse(target: &str) -> Result<Self, ServerError> {
+        if target.is_empty() {
+            return Err(ServerError::ParseError("empty request target".to_string()));
+        }
+        if target.bytes().any(|b| b <= b' ' || b == 0x7f) {
+            return Err(ServerError::ParseError(format!(
+                "invalid character in request target: {target:?}"
+            )));
+        }
+
+        let mut uri = HttpUri {
+            raw: target.to_string(),
+            ..HttpUri::new()
+        };
+
+        if target == "*" {
+            uri.form = UriForm::Asterisk;
+            uri.path = target.to_string();
+            return Ok(uri);
+        }
+
+        // a fragment is never sent, but don't let it end up in the path
+        let target = target.split('#').next().unwrap_or("");
+
+        if target.starts_with('/') {
+            uri.form = UriForm::Origin;
+            uri.set_path_and_query(target);
+        } else if let Some((scheme, rest)) = target.split_once("://") {
+            if !is_valid_scheme(scheme) {
+                return Err(ServerError::ParseError(format!("invalid scheme: {scheme}")));
+            }
+
+            let authority_end = rest.find(['/', '?']).unwrap_or(rest.len());
+            let (authority, path_and_query) = rest.split_at(authority_end);
+            if authority.is_empty() {
+                return Err(ServerError::ParseError(format!(
+                    "missing authority in request target: {target}"
+                )));
+            }
+
+            uri.form = UriForm::Absolute;
+            uri.scheme = Some(scheme.to_ascii_lowercase());
+            uri.authority = Some(authority.to_string());
+            uri.set_path_and_query(path_and_query);
+            if uri.path.is_empty() {
+                uri.path = "/".to_string();
+            }
+        } else {
+            // authority-form must have a port
+            match target.rsplit_once(':') {
+                Some((host, port))
+                    if !host.is_empty()
+                        && !port.is_empty()
+                        && port.bytes().all(|b| b.is_ascii_digit()) =>
+                {
+                    uri.form = UriForm::Authority;
+                    uri.authority = Some(target.to_string());
+                }
+                _ => {
+                    return Err(ServerError::ParseError(format!(
+                        "invalid request target: {target}"
+                    )));
+                }
+            }
+        }
+
+        Ok(uri)
+    }
+
+    fn set_path_and_query(&mut self, value: &str) {
+        match value.split_once('?') {
+            Some((path, query)) => {
+                self.path = path.to_string();
+                self.query = Some(query.to_string());
+            }
+            None => {
+                self.path = value.to_string();
+                self.query = None;
+            }
         }
     }
 
+    /// Percent-decoded path, invalid UTF-8 is replaced
+    ///
+    /// `%2F` is decoded to `/` as well
+    pub fn decoded_path(&self) -> String {
+        percent_decode_str(&self.path)
+    }
+
+    /// Request target as received, useful for logging
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    /// Request target as received
     pub fn as_string(&self) -> String {
-        self.path.clone()
+        self.raw.clone()
     }
 }
 
 impl From<&str> for HttpUri {
+    /// Same as `HttpUri::parse`, but a target which can't be parsed is kept as the path
     fn from(value: &str) -> Self {
-        HttpUri {
+        HttpUri::parse(value).unwrap_or_else(|_| HttpUri {
             path: value.to_string(),
-        }
+            raw: value.to_string(),
+            ..HttpUri::new()
+        })
     }
 }
 
 impl From<String> for HttpUri {
     fn from(value: String) -> Self {
-        HttpUri { path: value }
+        HttpUri::from(value.as_str())
+    }
+}
+
+fn is_valid_scheme(scheme: &str) -> bool {
+    let mut bytes = scheme.bytes();
+    bytes.next().is_some_and(|b| b.is_ascii_alphabetic())
+        && bytes.all(|b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'-' | b'.'))
+}
+
+/// Characters left as-is by `percent_encode`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodeSet {
+    /// Only unreserved characters, for a path segment or a query key or value
+    Component,
+    /// Unreserved characters and `/`, for a whole path
+    Path,
+}
+
+impl EncodeSet {
+    fn keeps(&self, b: u8) -> bool {
+        let unreserved = b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~');
+        match self {
+            EncodeSet::Component => unreserved,
+            EncodeSet::Path => unreserved || b == b'/',
+        }
+    }
+}
+
+/// Percent-encode every byte which is not kept by `set`
+pub fn percent_encode(input: &[u8], set: EncodeSet) -> String {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+    let mut output = String::with_capacity(input.len());
+    for &b in input {
+        if set.keeps(b) {
+            output.push(b as char);
+        } else {
+            output.push('%');
+            output.push(HEX[(b >> 4) as usize] as char);
+            output.push(HEX[(b & 0x0f) as usize] as char);
+        }
+    }
+
+    output
+}
+
+/// Decode `%XX` escapes, malformed escapes are kept as they are
+pub fn percent_decode(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        if input[i] == b'%'
+            && let (Some(high), Some(low)) = (
+                input.get(i + 1).and_then(|&b| hex_value(b)),
+                input.get(i + 2).and_then(|&b| hex_value(b)),
+            )
+        {
+            output.push(high << 4 | low);
+            i += 3;
+        } else {
+            output.push(input[i]);
+            i += 1;
+        }
+    }
+
+    output
+}
+
+/// Decode `%XX` escapes into a string, invalid UTF-8 is replaced
+pub fn percent_decode_str(input: &str) -> String {
+    String::from_utf8_lossy(&percent_decode(input.as_bytes())).into_owned()
+}
+
+fn hex_value(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_origin_form() {
+        let uri = HttpUri::parse("/search?q=rust&page=2").unwrap();
+        assert_eq!(uri.form, UriForm::Origin);
+        assert_eq!(uri.path, "/search");
+        assert_eq!(uri.query.as_deref(), Some("q=rust&page=2"));
+        assert_eq!(uri.scheme, None);
+        assert_eq!(uri.authority, None);
+        assert_eq!(uri.raw(), "/search?q=rust&page=2");
+
+        let uri = HttpUri::parse("/empty?").unwrap();
+        assert_eq!(uri.path, "/empty");
+        assert_eq!(uri.query.as_deref(), Some(""));
+    }
+
+    #[test]
+    fn test_absolute_form() {
+        let uri = HttpUri::parse("HTTP://example.com:8080/a/b?x=1").unwrap();
+        assert_eq!(uri.form, UriForm::Absolute);
+        assert_eq!(uri.scheme.as_deref(), Some("http"));
+        assert_eq!(uri.authority.as_deref(), Some("example.com:8080"));
+        assert_eq!(uri.path, "/a/b");
+        assert_eq!(uri.query.as_deref(), Some("x=1"));
+
+        let uri = HttpUri::parse("http://example.com?x=1").unwrap();
+        assert_eq!(uri.path, "/");
+        assert_eq!(uri.query.as_deref(), Some("x=1"));
+
+        assert!(HttpUri::parse("http:///path").is_err());
+        assert!(HttpUri::parse("1http://example.com/").is_err());
+    }
+
+    #[test]
+    fn test_authority_and_asterisk_form() {
+        let uri = HttpUri::parse("example.com:443").unwrap();
+        assert_eq!(uri.form, UriForm::Authority);
+        assert_eq!(uri.authority.as_deref(), Some("example.com:443"));
+        assert_eq!(uri.path, "");
+
+        let uri = HttpUri::parse("*").unwrap();
+        assert_eq!(uri.form, UriForm::Asterisk);
+        assert_eq!(uri.path, "*");
+
+        assert!(HttpUri::parse("example.com").is_err());
+        assert!(HttpUri::parse("").is_err());
+        assert!(HttpUri::parse("/a b").is_err());
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        let uri = HttpUri::parse("/files/hello%20world%21?q=%20").unwrap();
+        assert_eq!(uri.path, "/files/hello%20world%21");
+        assert_eq!(uri.decoded_path(), "/files/hello world!");
+
+        assert_eq!(percent_decode(b"%e4%BD%a0"), "你".as_bytes());
+        // malformed escapes are kept
+        assert_eq!(percent_decode_str("100%"), "100%");
+        assert_eq!(percent_decode_str("%zz%4"), "%zz%4");
+        // invalid UTF-8 is replaced
+        assert_eq!(percent_decode_str("%FF"), "\u{FFFD}");
+    }
+
+    #[test]
+    fn test_percent_encode() {
+        assert_eq!(
+            percent_encode(b"a b/c~", EncodeSet::Component),
+            "a%20b%2Fc~"
+        );
+        assert_eq!(percent_encode(b"/a b/c", EncodeSet::Path), "/a%20b/c");
+        assert_eq!(
+            percent_encode("你".as_bytes(), EncodeSet::Component),
+            "%E4%BD%A0"
+        );
+
+        let original = "/some path/ä?&=";
+        let encoded = percent_encode(original.as_bytes(), EncodeSet::Path);
+        assert_eq!(percent_decode_str(&encoded), original);
     }
 }
@@ -9,10 +9,14 @@ pub enum HttpVersion {
 
 impl HttpVersion {
     pub fn as_string(&self) -> String {
+        self.as_str().to_string()
+    }
+
+    pub fn as_str(&self) -> &'static str {
         match self {
-            HttpVersion::V1_0 => "HTTP/1.0".to_string(),
-            HttpVersion::V1_1 => "HTTP/1.1".to_string(),
-            HttpVersion::NoSupport => "NoSupport".to_string(),
+            HttpVersion::V1_0 => "HTTP/1.0",
+            HttpVersion::V1_1 => "HTTP/1.1",
+            HttpVersion::NoSupport => "NoSupport",
         }
     }
 